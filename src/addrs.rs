@@ -0,0 +1,41 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::Error;
+
+/// Public IP addresses of both versions, resolved concurrently.
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct Addrs {
+    /// The public IPv4 address, if it was resolved.
+    pub v4: Option<Ipv4Addr>,
+    /// The public IPv6 address, if it was resolved.
+    pub v6: Option<Ipv6Addr>,
+    /// The reason the IPv4 address was not resolved.
+    pub v4_error: Option<Error>,
+    /// The reason the IPv6 address was not resolved.
+    pub v6_error: Option<Error>,
+}
+
+impl Addrs {
+    /// Returns `true` if neither address was resolved.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_none() && self.v6.is_none()
+    }
+
+    /// Collects the outcomes of resolving each version.
+    pub(crate) fn from_results(v4: Result<IpAddr, Error>, v6: Result<IpAddr, Error>) -> Self {
+        let mut addrs = Self::default();
+        match v4 {
+            Ok(IpAddr::V4(addr)) => addrs.v4 = Some(addr),
+            Ok(IpAddr::V6(_)) => addrs.v4_error = Some(Error::Version),
+            Err(err) => addrs.v4_error = Some(err),
+        }
+        match v6 {
+            Ok(IpAddr::V6(addr)) => addrs.v6 = Some(addr),
+            Ok(IpAddr::V4(_)) => addrs.v6_error = Some(Error::Version),
+            Err(err) => addrs.v6_error = Some(err),
+        }
+        addrs
+    }
+}
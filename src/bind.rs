@@ -0,0 +1,69 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::rt;
+
+/// Local end which queries are sent from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Bind {
+    /// Queries are sent from the specified local address.
+    ///
+    /// Servers of the other IP version are unreachable.
+    Addr(IpAddr),
+    /// Queries are sent through the network interface with the specified
+    /// name, regardless of the routing table (`SO_BINDTODEVICE`).
+    ///
+    /// Usually requires the `CAP_NET_RAW` capability.
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    Interface(String),
+}
+
+/// Creates a UDP socket which sends datagrams to the `server`.
+pub(crate) fn udp_socket(bind: Option<&Bind>, server: SocketAddr) -> io::Result<rt::UdpSocket> {
+    let socket = socket(bind, server, Type::DGRAM, Protocol::UDP)?;
+    // NOTE: datagrams are received only once the socket is bound
+    if !matches!(bind, Some(Bind::Addr(_))) {
+        socket.bind(&SocketAddr::new(unspecified(server), 0).into())?;
+    }
+
+    rt::UdpSocket::new(socket.into())
+}
+
+/// Opens a TCP connection to the `server`.
+pub(crate) async fn tcp_connect(
+    bind: Option<&Bind>,
+    server: SocketAddr,
+) -> io::Result<rt::TcpStream> {
+    let socket = socket(bind, server, Type::STREAM, Protocol::TCP)?;
+    rt::TcpStream::connect(socket.into(), server).await
+}
+
+/// Creates a nonblocking socket which sends from the local end.
+fn socket(
+    bind: Option<&Bind>,
+    server: SocketAddr,
+    ty: Type,
+    protocol: Protocol,
+) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(server), ty, Some(protocol))?;
+    socket.set_nonblocking(true)?;
+
+    match bind {
+        Some(Bind::Addr(addr)) => socket.bind(&SocketAddr::new(*addr, 0).into())?,
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        Some(Bind::Interface(name)) => socket.bind_device(Some(name.as_bytes()))?,
+        None => {}
+    }
+
+    Ok(socket)
+}
+
+fn unspecified(server: SocketAddr) -> IpAddr {
+    match server {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
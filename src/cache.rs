@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{AddrVersion, Error, Options, Resolution, Source, DEFAULT};
+
+// NOTE: some servers, e.g. OpenDNS, answer with a TTL of zero
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// A cache of the last resolved public IP address of each version.
+///
+/// Clones share the cached addresses, so a single cache can be handed out
+/// to every part of an application which needs the address.
+#[derive(Debug, Clone)]
+pub struct Cache<'a> {
+    sources: &'a [Source<'a>],
+    options: Options,
+    max_age: Option<Duration>,
+    state: Arc<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: Mutex<HashMap<AddrVersion, Entry>>,
+    lookups: [futures_util::lock::Mutex<Lookup>; 3],
+    finished: [AtomicUsize; 3],
+}
+
+/// The outcome of the last resolution of a version.
+#[derive(Debug, Default)]
+struct Lookup {
+    outcome: Option<Result<Resolution, Error>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    resolution: Resolution,
+    /// `None` if the entry never expires.
+    expires_at: Option<Instant>,
+}
+
+impl Cache<'static> {
+    /// Creates an empty cache which resolves addresses using the built-in
+    /// resolvers.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            sources: DEFAULT,
+            options: Options::new(),
+            max_age: None,
+            state: Arc::default(),
+        }
+    }
+}
+
+impl Default for Cache<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Cache<'a> {
+    /// Sets the sources which are queried.
+    #[must_use]
+    pub fn with_sources<'b>(self, sources: &'b [Source<'b>]) -> Cache<'b> {
+        Cache {
+            sources,
+            options: self.options,
+            max_age: self.max_age,
+            state: self.state,
+        }
+    }
+
+    /// Sets the options used for each resolution.
+    #[must_use]
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets how long an address is cached, overriding the TTL of its
+    /// DNS record. Addresses are cached forever if the max age is too long
+    /// to be represented, e.g. [`Duration::MAX`].
+    #[must_use]
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Returns how long an address is cached, if it overrides the TTL.
+    #[must_use]
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Returns the cached address of the specified version or resolves it.
+    ///
+    /// Addresses are cached for the configured max age, the TTL of their
+    /// DNS record or a minute if neither is known or the TTL is zero.
+    /// Concurrent calls for the same version wait for a single resolution
+    /// and share its outcome, including failures. Failures and stale
+    /// addresses are not cached for later calls.
+    pub async fn resolve(&self, version: AddrVersion) -> Result<Resolution, Error> {
+        self.resolve_with(version, self.sources, &self.options)
+            .await
+    }
+
+    /// Same as [`Cache::resolve`], but queries the specified `sources`
+    /// according to the `options` instead of the configured ones.
+    pub(crate) async fn resolve_with(
+        &self,
+        version: AddrVersion,
+        sources: &[Source<'_>],
+        options: &Options,
+    ) -> Result<Resolution, Error> {
+        if let Some(resolution) = self.cached(version) {
+            return Ok(resolution);
+        }
+
+        let index = lookup_index(version);
+        let finished = self.state.finished[index].load(Ordering::Acquire);
+        let mut lookup = self.state.lookups[index].lock().await;
+        // NOTE: the address might have been resolved while waiting
+        if let Some(resolution) = self.cached(version) {
+            return Ok(resolution);
+        }
+        // NOTE: callers which waited for a resolution share its outcome,
+        // so that failures are not retried by each of them in turn
+        if self.state.finished[index].load(Ordering::Acquire) != finished {
+            if let Some(outcome) = &lookup.outcome {
+                return share(outcome);
+            }
+        }
+
+        let res = crate::resolve_detailed(version, sources, options).await;
+        lookup.outcome = Some(share(&res));
+        self.state.finished[index].fetch_add(1, Ordering::Release);
+
+        let resolution = res?;
+        if resolution.stale.is_some() {
+            return Ok(resolution);
+        }
+
+        let ttl = resolution.ttl.filter(|ttl| !ttl.is_zero());
+        let max_age = self.max_age.or(ttl).unwrap_or(DEFAULT_MAX_AGE);
+
+        let mut entries = self.state.entries.lock().unwrap();
+        entries.insert(
+            version,
+            Entry {
+                resolution: resolution.clone(),
+                expires_at: Instant::now().checked_add(max_age),
+            },
+        );
+        Ok(resolution)
+    }
+
+    /// Same as [`Cache::resolve`], but returns only the address.
+    pub async fn addr(&self, version: AddrVersion) -> Result<IpAddr, Error> {
+        self.resolve(version)
+            .await
+            .map(|resolution| resolution.addr)
+    }
+
+    /// Removes the cached address of the specified version, so that it is
+    /// resolved again on the next call.
+    pub fn invalidate(&self, version: AddrVersion) {
+        self.state.entries.lock().unwrap().remove(&version);
+    }
+
+    /// Removes the cached addresses of all versions.
+    pub fn clear(&self) {
+        self.state.entries.lock().unwrap().clear();
+    }
+
+    fn cached(&self, version: AddrVersion) -> Option<Resolution> {
+        let entries = self.state.entries.lock().unwrap();
+        match entries.get(&version) {
+            Some(entry) if entry.expires_at.is_none_or(|at| at > Instant::now()) => {
+                Some(entry.resolution.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn share(outcome: &Result<Resolution, Error>) -> Result<Resolution, Error> {
+    match outcome {
+        Ok(resolution) => Ok(resolution.clone()),
+        Err(err) => Err(err.duplicate()),
+    }
+}
+
+fn lookup_index(version: AddrVersion) -> usize {
+    match version {
+        AddrVersion::V4 => 0,
+        AddrVersion::V6 => 1,
+        AddrVersion::Any => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    use futures_util::future::{self, BoxFuture};
+
+    use super::*;
+    use crate::mock::{self, Behavior};
+    use crate::Provider;
+
+    const FIRST: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+    const SECOND: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 2);
+
+    #[tokio::test]
+    async fn reuses_cached_address() {
+        let resolver = mock::resolver(&[Behavior::Sequence(&[Some(FIRST), Some(SECOND)])]);
+        let sources = [Source::from(&resolver)];
+        let cache = Cache::new().with_sources(&sources);
+
+        // NOTE: concurrent lookups are coalesced into a single query
+        let (first, second) = future::join(
+            cache.addr(AddrVersion::V4),
+            cache.clone().addr(AddrVersion::V4),
+        )
+        .await;
+        assert_eq!(first.unwrap(), IpAddr::V4(FIRST));
+        assert_eq!(second.unwrap(), IpAddr::V4(FIRST));
+
+        let resolution = cache.resolve(AddrVersion::V4).await.unwrap();
+        assert_eq!(resolution.addr, IpAddr::V4(FIRST));
+        assert_eq!(resolution.ttl, Some(mock::TTL));
+
+        cache.invalidate(AddrVersion::V4);
+        let addr = cache.addr(AddrVersion::V4).await.unwrap();
+        assert_eq!(addr, IpAddr::V4(SECOND));
+    }
+
+    #[tokio::test]
+    async fn expires_after_max_age() {
+        let resolver = mock::resolver(&[Behavior::Sequence(&[Some(FIRST), Some(SECOND)])]);
+        let sources = [Source::from(&resolver)];
+        let cache = Cache::new()
+            .with_sources(&sources)
+            .with_max_age(Duration::from_millis(50));
+
+        let addr = cache.addr(AddrVersion::V4).await.unwrap();
+        assert_eq!(addr, IpAddr::V4(FIRST));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let addr = cache.addr(AddrVersion::V4).await.unwrap();
+        assert_eq!(addr, IpAddr::V4(SECOND));
+    }
+
+    #[tokio::test]
+    async fn caches_forever_for_max_duration() {
+        let resolver = mock::resolver(&[Behavior::Sequence(&[Some(FIRST), Some(SECOND)])]);
+        let sources = [Source::from(&resolver)];
+        let cache = Cache::new()
+            .with_sources(&sources)
+            .with_max_age(Duration::MAX);
+
+        assert_eq!(
+            cache.addr(AddrVersion::V4).await.unwrap(),
+            IpAddr::V4(FIRST)
+        );
+        assert_eq!(
+            cache.addr(AddrVersion::V4).await.unwrap(),
+            IpAddr::V4(FIRST)
+        );
+    }
+
+    #[tokio::test]
+    async fn caches_answers_with_zero_ttl() {
+        let provider = Counter(AtomicU8::new(0));
+        let sources = [Source::Provider(&provider)];
+        let cache = Cache::new().with_sources(&sources);
+
+        let resolution = cache.resolve(AddrVersion::V4).await.unwrap();
+        assert_eq!(resolution.ttl, Some(Duration::ZERO));
+        let addr = cache.addr(AddrVersion::V4).await.unwrap();
+        assert_eq!(addr, resolution.addr);
+    }
+
+    #[tokio::test]
+    async fn shares_failures_of_concurrent_calls() {
+        let provider = Failing(AtomicU8::new(0));
+        let sources = [Source::Provider(&provider)];
+        let cache = Cache::new().with_sources(&sources);
+
+        let results = future::join_all((0..5).map(|_| cache.addr(AddrVersion::V4))).await;
+        assert!(results.iter().all(|res| matches!(res, Err(Error::All(_)))));
+        assert_eq!(provider.0.load(Ordering::Relaxed), 1);
+
+        assert!(cache.addr(AddrVersion::V4).await.is_err());
+        assert_eq!(provider.0.load(Ordering::Relaxed), 2);
+    }
+
+    /// Fails after a while, counting the calls.
+    #[derive(Debug)]
+    struct Failing(AtomicU8);
+
+    impl Provider for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn resolve<'a>(
+            &'a self,
+            _: AddrVersion,
+            _: &'a Options,
+        ) -> BoxFuture<'a, Result<Resolution, Error>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Err(Error::Provider("unreachable".into()))
+            })
+        }
+    }
+
+    /// Answers with a different address on each call and a TTL of zero.
+    #[derive(Debug)]
+    struct Counter(AtomicU8);
+
+    impl Provider for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn resolve<'a>(
+            &'a self,
+            _: AddrVersion,
+            _: &'a Options,
+        ) -> BoxFuture<'a, Result<Resolution, Error>> {
+            let n = self.0.fetch_add(1, Ordering::Relaxed);
+            let addr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, n));
+            let mut resolution = Resolution::new(addr, self.name(), ([127, 0, 0, 1], 53).into());
+            resolution.ttl = Some(Duration::ZERO);
+            Box::pin(future::ready(Ok(resolution)))
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future;
+
+use crate::dns::{self, Transport};
+use crate::{
+    AddrVersion, Addrs, Cache, Error, Options, Provider, Resolution, Source, Strategy, DEFAULT,
+};
+
+/// A client which resolves the public IP address using the same
+/// configuration on each call.
+///
+/// Connections to DNS servers are kept open and reused by subsequent
+/// queries, unlike the free functions which connect on every call. Note
+/// that each query over UDP is still sent from a fresh random port.
+///
+/// The client is cheap to clone: clones share the configuration, the
+/// connections and the cached addresses.
+#[derive(Debug, Clone)]
+pub struct GetIp {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    providers: Vec<Arc<dyn Provider>>,
+    options: Options,
+    cache: Option<Cache<'static>>,
+}
+
+impl GetIp {
+    /// Creates a client which queries the [`DEFAULT`] sources.
+    #[must_use]
+    pub fn new() -> Self {
+        GetIpBuilder::new().build()
+    }
+
+    /// Returns a builder to configure a client.
+    #[must_use]
+    pub fn builder() -> GetIpBuilder {
+        GetIpBuilder::new()
+    }
+
+    /// Returns the options used for each resolution.
+    #[must_use]
+    pub fn options(&self) -> &Options {
+        &self.inner.options
+    }
+
+    /// Resolves the public IP address of the specified version, also
+    /// returning the details of how it was resolved.
+    pub async fn resolve(&self, version: AddrVersion) -> Result<Resolution, Error> {
+        let sources: Vec<_> = self
+            .inner
+            .providers
+            .iter()
+            .map(|provider| Source::Provider(provider.as_ref()))
+            .collect();
+
+        match &self.inner.cache {
+            Some(cache) => {
+                cache
+                    .resolve_with(version, &sources, &self.inner.options)
+                    .await
+            }
+            None => crate::resolve_detailed(version, &sources, &self.inner.options).await,
+        }
+    }
+
+    /// Resolves the public IP address of any version.
+    pub async fn addr(&self) -> Result<IpAddr, Error> {
+        self.resolve(AddrVersion::Any)
+            .await
+            .map(|resolution| resolution.addr)
+    }
+
+    /// Resolves the public IPv4 address.
+    pub async fn addr_v4(&self) -> Result<Ipv4Addr, Error> {
+        match self.resolve(AddrVersion::V4).await?.addr {
+            IpAddr::V4(addr) => Ok(addr),
+            IpAddr::V6(_) => Err(Error::Version),
+        }
+    }
+
+    /// Resolves the public IPv6 address.
+    pub async fn addr_v6(&self) -> Result<Ipv6Addr, Error> {
+        match self.resolve(AddrVersion::V6).await?.addr {
+            IpAddr::V4(_) => Err(Error::Version),
+            IpAddr::V6(addr) => Ok(addr),
+        }
+    }
+
+    /// Resolves the public IPv4 and IPv6 addresses concurrently.
+    pub async fn addrs(&self) -> Addrs {
+        let (v4, v6) =
+            future::join(self.resolve(AddrVersion::V4), self.resolve(AddrVersion::V6)).await;
+
+        Addrs::from_results(
+            v4.map(|resolution| resolution.addr),
+            v6.map(|resolution| resolution.addr),
+        )
+    }
+}
+
+impl Default for GetIp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Options to build a [`GetIp`] client.
+#[derive(Debug, Default)]
+pub struct GetIpBuilder {
+    providers: Vec<Arc<dyn Provider>>,
+    options: Options,
+    cache: Option<Cache<'static>>,
+}
+
+impl GetIpBuilder {
+    /// Creates a builder of a client which queries the [`DEFAULT`] sources
+    /// with the default options and without a cache.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a provider which is queried after the previously added ones.
+    ///
+    /// The [`DEFAULT`] sources are queried only if no provider is added.
+    #[must_use]
+    pub fn with_provider<P>(mut self, provider: P) -> Self
+    where
+        P: Provider + 'static,
+    {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Sets the options used for each resolution, replacing the strategy,
+    /// timeouts and transport set before.
+    #[must_use]
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the way providers are queried.
+    #[must_use]
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.options = self.options.with_strategy(strategy);
+        self
+    }
+
+    /// Sets the maximum time each resolution may take.
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.options = self.options.with_deadline(deadline);
+        self
+    }
+
+    /// Sets the time to wait for a response from each server.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options = self.options.with_timeout(timeout);
+        self
+    }
+
+    /// Sets the protocol used to send DNS queries.
+    #[must_use]
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.options = self.options.with_transport(transport);
+        self
+    }
+
+    /// Caches resolved addresses for the TTL of their DNS record.
+    ///
+    /// See [`Cache::resolve`] for details.
+    #[must_use]
+    pub fn with_cache(mut self) -> Self {
+        self.cache = Some(Cache::new());
+        self
+    }
+
+    /// Caches resolved addresses for the specified time, regardless of
+    /// the TTL of their DNS record.
+    #[must_use]
+    pub fn with_cache_max_age(mut self, max_age: Duration) -> Self {
+        self.cache = Some(Cache::new().with_max_age(max_age));
+        self
+    }
+
+    /// Builds the client.
+    #[must_use]
+    pub fn build(self) -> GetIp {
+        let providers = if self.providers.is_empty() {
+            default_providers()
+        } else {
+            self.providers
+        };
+
+        GetIp {
+            inner: Arc::new(Inner {
+                providers,
+                options: self.options.with_clients(dns::Clients::default()),
+                cache: self.cache,
+            }),
+        }
+    }
+}
+
+/// Returns the resolvers of the [`DEFAULT`] sources, which are all DNS resolvers.
+fn default_providers() -> Vec<Arc<dyn Provider>> {
+    DEFAULT
+        .iter()
+        .filter_map(|source| match source {
+            Source::Dns(resolver) => Some(Arc::new((*resolver).clone()) as Arc<dyn Provider>),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Behavior};
+
+    const FIRST: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+    const SECOND: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 2);
+
+    #[tokio::test]
+    async fn reuses_connections() {
+        let resolver = mock::resolver(&[Behavior::Port]);
+        let sources = [Source::from(&resolver)];
+        let client = GetIp::builder()
+            .with_provider(resolver.clone())
+            .with_transport(Transport::Tcp)
+            .build();
+
+        // NOTE: the mock server answers with the port the query came from
+        let first = client.addr_v4().await.unwrap();
+        let second = client.clone().addr_v4().await.unwrap();
+        assert_eq!(first, second);
+
+        let options = Options::new().with_transport(Transport::Tcp);
+        let fresh = crate::resolve(AddrVersion::V4, &sources, &options)
+            .await
+            .unwrap();
+        assert_ne!(fresh, IpAddr::V4(first));
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_server_closes_connection() {
+        let resolver = mock::resolver(&[Behavior::Closing(FIRST)]);
+        let client = GetIp::builder()
+            .with_provider(resolver)
+            .with_transport(Transport::Tcp)
+            .build();
+
+        assert_eq!(client.addr_v4().await.unwrap(), FIRST);
+        // NOTE: the idle connection is closed while no query is running
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(client.addr_v4().await.unwrap(), FIRST);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_connection_stalls() {
+        let resolver = mock::resolver(&[Behavior::Stalling(FIRST)]);
+        let client = GetIp::builder()
+            .with_provider(resolver)
+            .with_transport(Transport::Tcp)
+            .build();
+
+        assert_eq!(client.addr_v4().await.unwrap(), FIRST);
+        // NOTE: the query times out on the open but silent connection
+        assert!(client.addr_v4().await.is_err());
+        assert_eq!(client.addr_v4().await.unwrap(), FIRST);
+    }
+
+    #[cfg(feature = "async-io")]
+    #[test]
+    fn reuses_connections_outside_tokio_runtime() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let resolver = {
+            let _guard = runtime.enter();
+            mock::resolver(&[Behavior::Port])
+        };
+        let client = GetIp::builder()
+            .with_provider(resolver)
+            .with_transport(Transport::Tcp)
+            .build();
+
+        let first = futures_executor::block_on(client.addr_v4()).unwrap();
+        let second = futures_executor::block_on(client.addr_v4()).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn clones_share_cache() {
+        let resolver = mock::resolver(&[Behavior::Sequence(&[Some(FIRST), Some(SECOND)])]);
+        let client = GetIp::builder()
+            .with_provider(resolver)
+            .with_cache()
+            .build();
+
+        let task = tokio::spawn({
+            let client = client.clone();
+            async move { client.addr_v4().await }
+        });
+        assert_eq!(task.await.unwrap().unwrap(), FIRST);
+        assert_eq!(client.addr_v4().await.unwrap(), FIRST);
+
+        let addrs = client.addrs().await;
+        assert_eq!(addrs.v4, Some(FIRST));
+        assert!(addrs.v6.is_none() && addrs.v6_error.is_some());
+    }
+}
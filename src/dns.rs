@@ -1,25 +1,54 @@
+//! DNS based resolvers.
+
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use futures_util::future::BoxFuture;
+use futures_util::future::{BoxFuture, Either, Shared};
 use futures_util::stream::BoxStream;
 use futures_util::{future, ready, stream, FutureExt, Stream, StreamExt};
-use hickory_client::client::AsyncClient;
-use hickory_client::op::Query;
-use hickory_client::proto::error::{ProtoError, ProtoErrorKind};
-use hickory_client::proto::xfer::{DnsHandle, DnsRequestOptions, DnsResponse};
-use hickory_client::rr::{Name, RData, RecordType};
-use hickory_client::udp::UdpClientStream;
+use hickory_proto::error::{ProtoError, ProtoErrorKind};
+use hickory_proto::op::{NoopMessageFinalizer, Query};
+use hickory_proto::rr::{DNSClass, Name, RData, RecordType};
+use hickory_proto::tcp::TcpClientStream;
+use hickory_proto::udp::UdpClientStream;
+use hickory_proto::xfer::{
+    BufDnsStreamHandle, DnsClientStream, DnsExchange, DnsHandle, DnsMultiplexer, DnsRequestOptions,
+    DnsResponse,
+};
 
-use crate::error::Error;
-use crate::AddrVersion;
+use crate::bind::Bind;
+use crate::error::{Error, Failure};
+use crate::options::{self, Options};
+use crate::{rt, AddrVersion, Resolution};
 
 const DEFAULT_DNS_PORT: u16 = 53;
+#[cfg(feature = "doh")]
+const DEFAULT_DOH_PORT: u16 = 443;
+#[cfg(feature = "dot")]
+const DEFAULT_DOT_PORT: u16 = 853;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+const NAMESERVERS_TTL: Duration = Duration::from_secs(3600);
 
-pub const ALL: &[&Resolver<'static>] = &[OPENDNS_V4, OPENDNS_V6, GOOGLE_V4, GOOGLE_V6];
+/// All built-in DNS resolvers.
+pub const ALL: &[&Resolver<'static>] = &[
+    OPENDNS_V4,
+    OPENDNS_V6,
+    GOOGLE_V4,
+    GOOGLE_V6,
+    CLOUDFLARE_V4,
+    CLOUDFLARE_V6,
+    AKAMAI_V4,
+    AKAMAI_V6,
+];
 
+/// OpenDNS resolver for IPv4 addresses.
 pub const OPENDNS_V4: &Resolver<'static> = &Resolver::new_static(
     "myip.opendns.com",
     &[
@@ -28,10 +57,17 @@ pub const OPENDNS_V4: &Resolver<'static> = &Resolver::new_static(
         IpAddr::V4(Ipv4Addr::new(208, 67, 222, 220)),
         IpAddr::V4(Ipv4Addr::new(208, 67, 220, 222)),
     ],
-    DEFAULT_DNS_PORT,
+    &[
+        "resolver1.opendns.com",
+        "resolver2.opendns.com",
+        "resolver3.opendns.com",
+        "resolver4.opendns.com",
+    ],
     QueryMethod::A,
+    QueryClass::IN,
 );
 
+/// OpenDNS resolver for IPv6 addresses.
 pub const OPENDNS_V6: &Resolver<'static> = &Resolver::new_static(
     "myip.opendns.com",
     &[
@@ -40,10 +76,15 @@ pub const OPENDNS_V6: &Resolver<'static> = &Resolver::new_static(
         // 2620:0:ccd::2
         IpAddr::V6(Ipv6Addr::new(9760, 0, 3277, 0, 0, 0, 0, 2)),
     ],
-    DEFAULT_DNS_PORT,
+    &[
+        "resolver1.ipv6-sandbox.opendns.com",
+        "resolver2.ipv6-sandbox.opendns.com",
+    ],
     QueryMethod::AAAA,
+    QueryClass::IN,
 );
 
+/// Google resolver for IPv4 addresses.
 pub const GOOGLE_V4: &Resolver<'static> = &Resolver::new_static(
     "o-o.myaddr.l.google.com",
     &[
@@ -52,10 +93,17 @@ pub const GOOGLE_V4: &Resolver<'static> = &Resolver::new_static(
         IpAddr::V4(Ipv4Addr::new(216, 239, 36, 10)),
         IpAddr::V4(Ipv4Addr::new(216, 239, 38, 10)),
     ],
-    DEFAULT_DNS_PORT,
+    &[
+        "ns1.google.com",
+        "ns2.google.com",
+        "ns3.google.com",
+        "ns4.google.com",
+    ],
     QueryMethod::TXT,
+    QueryClass::IN,
 );
 
+/// Google resolver for IPv6 addresses.
 pub const GOOGLE_V6: &Resolver<'static> = &Resolver::new_static(
     "o-o.myaddr.l.google.com",
     &[
@@ -68,14 +116,64 @@ pub const GOOGLE_V6: &Resolver<'static> = &Resolver::new_static(
         // 2001:4860:4802:38::a
         IpAddr::V6(Ipv6Addr::new(8193, 18528, 18434, 56, 0, 0, 0, 10)),
     ],
-    DEFAULT_DNS_PORT,
+    &[
+        "ns1.google.com",
+        "ns2.google.com",
+        "ns3.google.com",
+        "ns4.google.com",
+    ],
     QueryMethod::TXT,
+    QueryClass::IN,
+);
+
+/// Cloudflare resolver for IPv4 addresses.
+pub const CLOUDFLARE_V4: &Resolver<'static> = &Resolver::new_static(
+    "whoami.cloudflare",
+    &[
+        IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+        IpAddr::V4(Ipv4Addr::new(1, 0, 0, 1)),
+    ],
+    &["one.one.one.one"],
+    QueryMethod::TXT,
+    QueryClass::CH,
+);
+
+/// Cloudflare resolver for IPv6 addresses.
+pub const CLOUDFLARE_V6: &Resolver<'static> = &Resolver::new_static(
+    "whoami.cloudflare",
+    &[
+        // 2606:4700:4700::1111
+        IpAddr::V6(Ipv6Addr::new(9734, 18176, 18176, 0, 0, 0, 0, 4369)),
+        // 2606:4700:4700::1001
+        IpAddr::V6(Ipv6Addr::new(9734, 18176, 18176, 0, 0, 0, 0, 4097)),
+    ],
+    &["one.one.one.one"],
+    QueryMethod::TXT,
+    QueryClass::CH,
+);
+
+/// Akamai resolver for IPv4 addresses.
+pub const AKAMAI_V4: &Resolver<'static> = &Resolver::new_static(
+    "whoami.akamai.net",
+    &[],
+    &["ns1-1.akamaitech.net"],
+    QueryMethod::A,
+    QueryClass::IN,
+);
+
+/// Akamai resolver for IPv6 addresses.
+pub const AKAMAI_V6: &Resolver<'static> = &Resolver::new_static(
+    "whoami.akamai.net",
+    &[],
+    &["ns1-1.akamaitech.net"],
+    QueryMethod::AAAA,
+    QueryClass::IN,
 );
 
 /// Method used to query an IP address from a DNS server
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(clippy::upper_case_acronyms)]
-enum QueryMethod {
+pub enum QueryMethod {
     /// The first queried `A` name record is extracted as our IP address.
     A,
     /// The first queried `AAAA` name record is extracted as our IP address.
@@ -84,13 +182,127 @@ enum QueryMethod {
     TXT,
 }
 
+/// Class of the queried DNS records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum QueryClass {
+    /// The Internet class, used by regular records.
+    #[default]
+    IN,
+    /// The Chaos class, used by servers to describe themselves.
+    CH,
+}
+
+/// Protocol used to send queries to a DNS server.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Transport {
+    /// Queries are sent over UDP and retried over TCP if the response
+    /// is truncated.
+    #[default]
+    Udp,
+    /// Queries are sent over TCP.
+    Tcp,
+    /// Queries are sent over HTTPS (RFC 8484).
+    ///
+    /// Note that the server must answer the queried name itself, e.g.
+    /// a recursive resolver would see its own address instead of ours.
+    #[cfg(feature = "doh")]
+    Https(TlsConfig),
+    /// Queries are sent over TLS (RFC 7858).
+    #[cfg(feature = "dot")]
+    Tls(TlsConfig),
+}
+
+impl Transport {
+    /// Returns the port used if none is specified for the resolver.
+    #[must_use]
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Self::Udp | Self::Tcp => DEFAULT_DNS_PORT,
+            #[cfg(feature = "doh")]
+            Self::Https(_) => DEFAULT_DOH_PORT,
+            #[cfg(feature = "dot")]
+            Self::Tls(_) => DEFAULT_DOT_PORT,
+        }
+    }
+}
+
+/// TLS settings of encrypted transports.
+#[cfg(any(feature = "doh", feature = "dot"))]
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    server_name: String,
+    client_config: Arc<rustls::ClientConfig>,
+}
+
+#[cfg(any(feature = "doh", feature = "dot"))]
+impl TlsConfig {
+    /// Creates settings which verify the server certificate for the
+    /// `server_name` against the Mozilla root certificates.
+    #[must_use]
+    pub fn new<N>(server_name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        Self {
+            server_name: server_name.into(),
+            client_config: crate::tls::default_client_config(),
+        }
+    }
+
+    /// Sets the root certificates the server certificate is verified against.
+    #[must_use]
+    pub fn with_root_certificates(mut self, roots: rustls::RootCertStore) -> Self {
+        self.client_config = Arc::new(crate::tls::client_config(roots));
+        self
+    }
+
+    /// Returns the name the server certificate is verified for.
+    #[must_use]
+    pub fn server_name(&self) -> &str {
+        &self.server_name
+    }
+}
+
+/// Settings are equal if they verify the same server name using the same
+/// client configuration.
+#[cfg(any(feature = "doh", feature = "dot"))]
+impl PartialEq for TlsConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.server_name == other.server_name
+            && Arc::ptr_eq(&self.client_config, &other.client_config)
+    }
+}
+
+#[cfg(any(feature = "doh", feature = "dot"))]
+impl Eq for TlsConfig {}
+
+/// When the host names of the DNS servers are resolved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum NameserverLookup {
+    /// The names are resolved only if none of the addresses specified on
+    /// creation answered, or if there are none.
+    #[default]
+    Fallback,
+    /// The names are resolved before querying, and the addresses specified
+    /// on creation are queried only if none of the names could be resolved.
+    First,
+}
+
 /// Options to build a DNS resolver.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Resolver<'r> {
-    port: u16,
+    port: Option<u16>,
     name: Cow<'r, str>,
     servers: Cow<'r, [IpAddr]>,
+    nameservers: &'r [&'r str],
+    nameserver_lookup: NameserverLookup,
     method: QueryMethod,
+    class: QueryClass,
+    timeout: Duration,
+    transport: Transport,
 }
 
 impl Resolver<'static> {
@@ -98,128 +310,738 @@ impl Resolver<'static> {
     const fn new_static(
         name: &'static str,
         servers: &'static [IpAddr],
-        port: u16,
+        nameservers: &'static [&'static str],
         method: QueryMethod,
+        class: QueryClass,
     ) -> Self {
         Self {
-            port,
+            port: None,
             name: Cow::Borrowed(name),
             servers: Cow::Borrowed(servers),
+            nameservers,
+            nameserver_lookup: NameserverLookup::Fallback,
             method,
+            class,
+            timeout: DEFAULT_TIMEOUT,
+            transport: Transport::Udp,
         }
     }
 }
 
 impl<'r> Resolver<'r> {
-    pub fn resolve(&self, version: AddrVersion) -> BoxStream<'static, Result<IpAddr, Error>> {
-        let port = self.port;
-        let method = self.method;
+    /// Creates a resolver which queries `name` on the specified `servers`
+    /// using the default port of the transport.
+    #[must_use]
+    pub fn new<N, S>(name: N, servers: S, method: QueryMethod) -> Self
+    where
+        N: Into<Cow<'r, str>>,
+        S: Into<Cow<'r, [IpAddr]>>,
+    {
+        Self {
+            port: None,
+            name: name.into(),
+            servers: servers.into(),
+            nameservers: &[],
+            nameserver_lookup: NameserverLookup::Fallback,
+            method,
+            class: QueryClass::IN,
+            timeout: DEFAULT_TIMEOUT,
+            transport: Transport::Udp,
+        }
+    }
+
+    /// Sets the port used to connect to the servers.
+    #[must_use]
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Sets the host names of the DNS servers, which are resolved using
+    /// the system resolver.
+    ///
+    /// By default, the names are resolved only if none of the addresses
+    /// specified on creation answered, see [`Resolver::with_nameserver_lookup`].
+    /// Resolved addresses are cached for an hour.
+    #[must_use]
+    pub fn with_nameservers(mut self, nameservers: &'r [&'r str]) -> Self {
+        self.nameservers = nameservers;
+        self
+    }
+
+    /// Sets when the host names of the DNS servers are resolved.
+    ///
+    /// Note that the system resolver, unlike the addresses specified on
+    /// creation, may be controlled by the network the device is on.
+    #[must_use]
+    pub fn with_nameserver_lookup(mut self, lookup: NameserverLookup) -> Self {
+        self.nameserver_lookup = lookup;
+        self
+    }
+
+    /// Sets the class of the queried records.
+    #[must_use]
+    pub fn with_class(mut self, class: QueryClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    /// Sets the time to wait for a response from each server.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the protocol used to send queries.
+    #[must_use]
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Returns the name which is queried.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the addresses of the DNS servers.
+    #[must_use]
+    pub fn servers(&self) -> &[IpAddr] {
+        &self.servers
+    }
+
+    /// Returns the host names of the DNS servers.
+    #[must_use]
+    pub fn nameservers(&self) -> &'r [&'r str] {
+        self.nameservers
+    }
+
+    /// Returns when the host names of the DNS servers are resolved.
+    #[must_use]
+    pub fn nameserver_lookup(&self) -> NameserverLookup {
+        self.nameserver_lookup
+    }
+
+    /// Returns the port used to connect to the servers.
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or_else(|| self.transport.default_port())
+    }
+
+    /// Returns the method used to extract the IP address from the response.
+    #[must_use]
+    pub fn method(&self) -> QueryMethod {
+        self.method
+    }
+
+    /// Returns the class of the queried records.
+    #[must_use]
+    pub fn class(&self) -> QueryClass {
+        self.class
+    }
+
+    /// Returns the time to wait for a response from each server.
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns the protocol used to send queries.
+    #[must_use]
+    pub fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    /// Queries the servers one by one in the specified order, yielding
+    /// the outcome of each query.
+    pub fn resolve(
+        &self,
+        version: AddrVersion,
+        options: &Options,
+    ) -> BoxStream<'static, Result<Resolution, Failure>> {
         let name = match Name::from_ascii(self.name.as_ref()) {
             Ok(name) => name,
-            Err(err) => return Box::pin(stream::once(future::ready(Err(Error::Dns(err))))),
+            Err(err) => {
+                let failure = Failure::new(self.name.as_ref(), None, err.into());
+                return Box::pin(stream::once(future::ready(Err(failure))));
+            }
         };
 
-        let mut servers: Vec<_> = self
+        // NOTE: servers are popped from the back
+        let servers: Vec<_> = self
             .servers
             .iter()
+            .rev()
             .copied()
             .filter(|addr| version.matches(*addr))
             .collect();
 
-        let first_server = match servers.pop() {
-            Some(server) => server,
-            None => return Box::pin(stream::empty()),
-        };
-
         let record_type = match self.method {
             QueryMethod::A => RecordType::A,
             QueryMethod::AAAA => RecordType::AAAA,
             QueryMethod::TXT => RecordType::TXT,
         };
+        let mut query = Query::query(name, record_type);
+        query.set_query_class(match self.class {
+            QueryClass::IN => DNSClass::IN,
+            QueryClass::CH => DNSClass::CH,
+        });
+
+        let transport = options.transport().unwrap_or(&self.transport).clone();
+        let port = self.port.unwrap_or_else(|| transport.default_port());
+        let timeout = options.timeout().unwrap_or(self.timeout);
+
+        let nameservers = (!self.nameservers.is_empty()).then(|| {
+            let nameservers = self.nameservers.iter().map(|s| s.to_string()).collect();
+            // NOTE: the server sees the address we connect from, so it must be
+            // of the same family as the queried record and the fallback servers
+            let method = self.method;
+            let family = match (
+                self.servers.iter().all(IpAddr::is_ipv4),
+                self.servers.iter().all(IpAddr::is_ipv6),
+            ) {
+                (true, false) => AddrVersion::V4,
+                (false, true) => AddrVersion::V6,
+                _ => AddrVersion::Any,
+            };
+            let filter = move |addr: &IpAddr| {
+                version.matches(*addr)
+                    && family.matches(*addr)
+                    && match method {
+                        QueryMethod::A => addr.is_ipv4(),
+                        QueryMethod::AAAA => addr.is_ipv6(),
+                        QueryMethod::TXT => true,
+                    }
+            };
 
-        let query = Query::query(name, record_type);
-        let fut = resolve(first_server, port, query.clone(), method);
+            let fut = lookup_nameservers(nameservers, port, timeout, filter);
+            Box::pin(fut) as NameserversFut
+        });
 
         Box::pin(DnsResolutions {
             port,
-            query,
-            method,
             servers,
-            fut: Some(Box::pin(fut)),
+            nameservers,
+            lookup: self.nameserver_lookup,
+            answered: false,
+            queried: Vec::new(),
+            params: Arc::new(QueryParams {
+                resolver: self.name.to_string(),
+                query,
+                method: self.method,
+                timeout,
+                transport,
+                bind: options.bind().cloned(),
+                clients: options.clients().cloned(),
+                retries: options.retries(),
+                backoff: options.backoff(),
+            }),
+            fut: None,
         })
     }
 }
 
 struct DnsResolutions {
     port: u16,
-    query: Query,
-    method: QueryMethod,
     servers: Vec<IpAddr>,
+    nameservers: Option<NameserversFut>,
+    lookup: NameserverLookup,
+    answered: bool,
+    queried: Vec<IpAddr>,
+    params: Arc<QueryParams>,
     fut: Option<ResolutionFut>,
 }
 
+struct QueryParams {
+    resolver: String,
+    query: Query,
+    method: QueryMethod,
+    timeout: Duration,
+    transport: Transport,
+    bind: Option<Bind>,
+    clients: Option<Clients>,
+    retries: usize,
+    backoff: Duration,
+}
+
 impl Stream for DnsResolutions {
-    type Item = Result<IpAddr, Error>;
+    type Item = Result<Resolution, Failure>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        while self.fut.is_some() {
+        loop {
             if let Some(fut) = &mut self.fut {
                 let res = ready!(fut.poll_unpin(cx));
                 self.fut = None;
+                self.answered |= res.is_ok();
                 return Poll::Ready(Some(res));
             }
 
-            if let Some(server) = self.servers.pop() {
-                let fut = resolve(server, self.port, self.query.clone(), self.method);
-                self.fut = Some(Box::pin(fut));
+            let lookup = match self.lookup {
+                NameserverLookup::First => true,
+                NameserverLookup::Fallback => self.servers.is_empty() && !self.answered,
+            };
+            if let Some(fut) = self.nameservers.as_mut().filter(|_| lookup) {
+                let res = ready!(fut.poll_unpin(cx));
+                self.nameservers = None;
+                match res {
+                    // NOTE: servers are popped from the back
+                    Ok(servers) if !servers.is_empty() => {
+                        self.servers = servers
+                            .into_iter()
+                            .filter(|server| !self.queried.contains(server))
+                            .rev()
+                            .collect();
+                    }
+                    Err(error) if self.servers.is_empty() => {
+                        let failure = Failure::new(self.params.resolver.as_str(), None, error);
+                        return Poll::Ready(Some(Err(failure)));
+                    }
+                    _ => {}
+                }
+            }
+
+            let Some(server) = self.servers.pop() else {
+                return Poll::Ready(None);
+            };
+
+            self.queried.push(server);
+            let server = SocketAddr::new(server, self.port);
+            let fut = resolve(server, self.params.clone());
+            self.fut = Some(Box::pin(fut));
+        }
+    }
+}
+
+type ResolutionFut = BoxFuture<'static, Result<Resolution, Failure>>;
+type NameserversFut = BoxFuture<'static, Result<Vec<IpAddr>, Error>>;
+
+/// Resolves the addresses of the nameservers using the system resolver.
+async fn lookup_nameservers<F>(
+    nameservers: Vec<String>,
+    port: u16,
+    timeout: Duration,
+    filter: F,
+) -> Result<Vec<IpAddr>, Error>
+where
+    F: Fn(&IpAddr) -> bool,
+{
+    let lookups = nameservers.iter().map(|nameserver| async move {
+        if let Some(addrs) = cached_nameserver(nameserver) {
+            return Ok(addrs);
+        }
+
+        let lookup = rt::lookup_host(nameserver, port);
+        let addrs: Vec<_> = rt::timeout(timeout, lookup)
+            .await
+            .map_err(|_| Error::Timeout)??
+            .into_iter()
+            .map(|addr| addr.ip())
+            .collect();
+        cache_nameserver(nameserver, &addrs);
+        Ok::<_, Error>(addrs)
+    });
+
+    let mut servers = Vec::new();
+    let mut error = None;
+    for res in future::join_all(lookups).await {
+        match res {
+            Ok(addrs) => {
+                for addr in addrs.into_iter().filter(&filter) {
+                    if !servers.contains(&addr) {
+                        servers.push(addr);
+                    }
+                }
             }
+            Err(err) => error = Some(err),
         }
+    }
 
-        Poll::Ready(None)
+    match error {
+        Some(error) if servers.is_empty() => Err(error),
+        _ => Ok(servers),
     }
 }
 
-type ResolutionFut = BoxFuture<'static, Result<IpAddr, Error>>;
+type NameserversCache = Mutex<HashMap<String, (Instant, Vec<IpAddr>)>>;
 
-async fn resolve(
-    server: IpAddr,
-    port: u16,
-    query: Query,
-    method: QueryMethod,
-) -> Result<IpAddr, Error> {
-    let server = SocketAddr::new(server, port);
+fn nameservers_cache() -> &'static NameserversCache {
+    static CACHE: OnceLock<NameserversCache> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+fn cached_nameserver(nameserver: &str) -> Option<Vec<IpAddr>> {
+    let cache = nameservers_cache().lock().unwrap();
+    match cache.get(nameserver) {
+        Some((resolved_at, addrs)) if resolved_at.elapsed() < NAMESERVERS_TTL => {
+            Some(addrs.clone())
+        }
+        _ => None,
+    }
+}
+
+fn cache_nameserver(nameserver: &str, addrs: &[IpAddr]) {
+    let mut cache = nameservers_cache().lock().unwrap();
+    cache.insert(nameserver.to_owned(), (Instant::now(), addrs.to_vec()));
+}
+
+async fn resolve(server: SocketAddr, params: Arc<QueryParams>) -> Result<Resolution, Failure> {
+    query(server, &params)
+        .await
+        .map_err(|error| Failure::new(params.resolver.as_str(), Some(server), error))
+}
+
+async fn query(server: SocketAddr, params: &QueryParams) -> Result<Resolution, Error> {
     let mut query_opts = DnsRequestOptions::default();
     query_opts.use_edns = true;
-    let response = dns_query(server, query, query_opts).await?;
-    parse_dns_response(response, method)
+
+    let started_at = Instant::now();
+    let response = options::retry(params.retries, params.backoff, || {
+        dns_query(server, params, query_opts)
+    })
+    .await?;
+    let latency = started_at.elapsed();
+
+    let (addr, ttl) = parse_dns_response(response, params.method)?;
+    Ok(Resolution {
+        addr,
+        resolver: params.resolver.clone(),
+        server,
+        method: Some(params.method),
+        latency,
+        ttl: Some(Duration::from_secs(ttl.into())),
+        stale: None,
+    })
 }
 
 async fn dns_query(
     server: SocketAddr,
-    query: Query,
+    params: &QueryParams,
+    query_opts: DnsRequestOptions,
+) -> Result<DnsResponse, Error> {
+    let response = exchange(server, params, &params.transport, query_opts).await?;
+    match params.transport {
+        // NOTE: the full response doesn't fit into a datagram
+        Transport::Udp if response.truncated() => {
+            exchange(server, params, &Transport::Tcp, query_opts).await
+        }
+        _ => Ok(response),
+    }
+}
+
+/// Sends the query using a client from the pool, if there is one,
+/// or a new client otherwise.
+async fn exchange(
+    server: SocketAddr,
+    params: &QueryParams,
+    transport: &Transport,
     query_opts: DnsRequestOptions,
-) -> Result<DnsResponse, ProtoError> {
-    let stream = UdpClientStream::<tokio::net::UdpSocket>::new(server);
-    let (client, bg) = AsyncClient::connect(stream).await?;
-    tokio::spawn(bg);
-
-    client
-        .lookup(query, query_opts)
-        .next()
+) -> Result<DnsResponse, Error> {
+    let fut = async {
+        let Some(clients) = &params.clients else {
+            let (client, bg) = connect(server, params, transport).await?;
+            return lookup(&client, bg.map(drop), params.query.clone(), query_opts).await;
+        };
+
+        let key = &ClientKey::new(server, params, transport);
+        let pooled_lookup = |client: DnsExchange, bg: SharedBackground| async move {
+            // NOTE: the connection might be broken, so unless the query
+            // succeeds, even if it is cancelled by the timeout, the next
+            // one opens a new connection
+            let eviction = Eviction::new(clients, key, &bg);
+            let res = lookup(&client, bg, params.query.clone(), query_opts).await;
+            if res.is_ok() {
+                eviction.cancel();
+            }
+            res
+        };
+        let fresh = || async {
+            let (client, bg) = connect(server, params, transport).await?;
+            let (client, bg) = clients.insert(key.clone(), client, bg);
+            pooled_lookup(client, bg).await
+        };
+
+        match clients.get(key) {
+            Some((client, bg)) => match pooled_lookup(client, bg).await {
+                Ok(response) => Ok(response),
+                // NOTE: connections make progress only while a query is
+                // running, so one closed by the server while idle is
+                // noticed only now and the query is sent again
+                Err(_) => fresh().await,
+            },
+            None => fresh().await,
+        }
+    };
+
+    // NOTE: unlike other transports, HTTPS has no builtin timeout
+    rt::timeout(params.timeout, fut)
         .await
+        .map_err(|_| Error::Timeout)?
+}
+
+type Background = BoxFuture<'static, Result<(), ProtoError>>;
+
+/// Opens a connection to the `server`, returning the client which sends
+/// queries over it and the future which drives the connection.
+async fn connect(
+    server: SocketAddr,
+    params: &QueryParams,
+    transport: &Transport,
+) -> Result<(DnsExchange, Background), Error> {
+    let timeout = params.timeout;
+    let bind = params.bind.clone();
+
+    match transport {
+        Transport::Udp => {
+            let stream = UdpClientStream::<rt::UdpSocket>::with_creator(
+                server,
+                None,
+                timeout,
+                Arc::new(move |_, server| {
+                    let bind = bind.clone();
+                    Box::pin(async move { crate::bind::udp_socket(bind.as_ref(), server) })
+                }),
+            );
+            let (client, bg) = DnsExchange::connect::<_, _, rt::Time>(stream).await?;
+            Ok((client, Box::pin(bg)))
+        }
+        Transport::Tcp => {
+            let connect = async move { crate::bind::tcp_connect(bind.as_ref(), server).await };
+            let (stream, sender) = TcpClientStream::with_future(connect, server, timeout);
+            multiplex(stream, sender, timeout).await
+        }
+        #[cfg(feature = "doh")]
+        Transport::Https(tls) => {
+            use hickory_proto::h2::HttpsClientStreamBuilder;
+
+            // NOTE: hickory spawns the HTTP/2 connection onto the Tokio
+            // runtime, so there must be one
+            tokio::runtime::Handle::try_current().map_err(std::io::Error::other)?;
+
+            let connect = async move { crate::bind::tcp_connect(bind.as_ref(), server).await };
+            let stream = HttpsClientStreamBuilder::build_with_future(
+                Box::pin(connect),
+                tls.client_config.clone(),
+                server,
+                tls.server_name.clone(),
+            );
+            let (client, bg) = DnsExchange::connect::<_, _, rt::Time>(stream).await?;
+            Ok((client, Box::pin(bg)))
+        }
+        #[cfg(feature = "dot")]
+        Transport::Tls(tls) => {
+            use hickory_proto::tcp::TcpStream;
+
+            // NOTE: the handshake is done here so that TLS errors are not
+            // flattened into strings by hickory
+            let server_name = rustls::ServerName::try_from(tls.server_name.as_str())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            let stream = crate::bind::tcp_connect(bind.as_ref(), server).await?;
+            let stream = tokio_rustls::TlsConnector::from(tls.client_config.clone())
+                .connect(server_name, stream)
+                .await?;
+
+            let (stream, sender) = TcpStream::from_stream(rt::Compat(stream), server);
+            let stream = future::ready(Ok(TcpClientStream::from_stream(stream)));
+            multiplex(stream, sender, timeout).await
+        }
+    }
+}
+
+/// Connects a client which multiplexes queries over a single stream.
+async fn multiplex<F, S>(
+    stream: F,
+    sender: BufDnsStreamHandle,
+    timeout: Duration,
+) -> Result<(DnsExchange, Background), Error>
+where
+    F: Future<Output = Result<S, ProtoError>> + Send + Unpin + 'static,
+    S: DnsClientStream + Unpin + 'static,
+{
+    let stream =
+        DnsMultiplexer::<_, NoopMessageFinalizer>::with_timeout(stream, sender, timeout, None);
+    let (client, bg) = DnsExchange::connect::<_, _, rt::Time>(stream).await?;
+    Ok((client, Box::pin(bg)))
+}
+
+/// Sends the query, driving the connection until the response arrives.
+async fn lookup<B>(
+    client: &DnsExchange,
+    bg: B,
+    query: Query,
+    query_opts: DnsRequestOptions,
+) -> Result<DnsResponse, Error>
+where
+    B: Future<Output = ()> + Unpin,
+{
+    let mut responses = client.lookup(query, query_opts);
+    let response = match future::select(responses.next(), bg).await {
+        Either::Left((response, _)) => response,
+        // NOTE: pending queries fail once the connection is closed
+        Either::Right(((), response)) => response.await,
+    };
+
+    response
         .transpose()?
-        .ok_or_else(|| ProtoErrorKind::Message("expected a response").into())
+        // NOTE: hickory ends the response stream without an item on timeout
+        .ok_or(Error::Timeout)
+}
+
+/// Clients of the DNS servers shared by the clones of a [`GetIp`](crate::GetIp).
+///
+/// Each client keeps its connection open until it fails or the pool is
+/// dropped. Connections are driven by the queries sent over them, so no
+/// background task is spawned.
+#[derive(Clone, Default)]
+pub(crate) struct Clients(Arc<Mutex<HashMap<ClientKey, PooledClient>>>);
+
+type SharedBackground = Shared<future::Map<Background, fn(Result<(), ProtoError>)>>;
+
+struct PooledClient {
+    client: DnsExchange,
+    bg: SharedBackground,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ClientKey {
+    server: SocketAddr,
+    transport: TransportKey,
+    timeout: Duration,
+    bind: Option<Bind>,
+}
+
+/// Identifies the transport, comparing TLS client configurations by address.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum TransportKey {
+    Udp,
+    Tcp,
+    #[cfg(feature = "doh")]
+    Https(String, usize),
+    #[cfg(feature = "dot")]
+    Tls(String, usize),
+}
+
+impl ClientKey {
+    fn new(server: SocketAddr, params: &QueryParams, transport: &Transport) -> Self {
+        let transport = match transport {
+            Transport::Udp => TransportKey::Udp,
+            Transport::Tcp => TransportKey::Tcp,
+            #[cfg(feature = "doh")]
+            Transport::Https(tls) => TransportKey::Https(
+                tls.server_name.clone(),
+                Arc::as_ptr(&tls.client_config) as usize,
+            ),
+            #[cfg(feature = "dot")]
+            Transport::Tls(tls) => TransportKey::Tls(
+                tls.server_name.clone(),
+                Arc::as_ptr(&tls.client_config) as usize,
+            ),
+        };
+
+        Self {
+            server,
+            transport,
+            timeout: params.timeout,
+            bind: params.bind.clone(),
+        }
+    }
 }
 
-fn parse_dns_response(response: DnsResponse, method: QueryMethod) -> Result<IpAddr, Error> {
+impl Clients {
+    fn get(&self, key: &ClientKey) -> Option<(DnsExchange, SharedBackground)> {
+        let clients = self.0.lock().unwrap();
+        match clients.get(key) {
+            // NOTE: the background future completes once the connection is closed
+            Some(pooled) if pooled.bg.peek().is_none() => {
+                Some((pooled.client.clone(), pooled.bg.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn insert(
+        &self,
+        key: ClientKey,
+        client: DnsExchange,
+        bg: Background,
+    ) -> (DnsExchange, SharedBackground) {
+        let bg = bg.map(drop as fn(_)).shared();
+
+        let mut clients = self.0.lock().unwrap();
+        let pooled = PooledClient {
+            client: client.clone(),
+            bg: bg.clone(),
+        };
+        clients.insert(key, pooled);
+        (client, bg)
+    }
+
+    /// Removes the client of the connection driven by `bg`, keeping
+    /// a client which replaced it.
+    fn remove(&self, key: &ClientKey, bg: &SharedBackground) {
+        let mut clients = self.0.lock().unwrap();
+        if clients.get(key).is_some_and(|pooled| pooled.bg.ptr_eq(bg)) {
+            clients.remove(key);
+        }
+    }
+}
+
+/// Removes a pooled client when dropped, unless cancelled.
+struct Eviction<'a> {
+    clients: &'a Clients,
+    key: &'a ClientKey,
+    bg: Option<SharedBackground>,
+}
+
+impl<'a> Eviction<'a> {
+    fn new(clients: &'a Clients, key: &'a ClientKey, bg: &SharedBackground) -> Self {
+        Self {
+            clients,
+            key,
+            bg: Some(bg.clone()),
+        }
+    }
+
+    fn cancel(mut self) {
+        self.bg = None;
+    }
+}
+
+impl Drop for Eviction<'_> {
+    fn drop(&mut self) {
+        if let Some(bg) = &self.bg {
+            self.clients.remove(self.key, bg);
+        }
+    }
+}
+
+impl fmt::Debug for Clients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Clients").finish_non_exhaustive()
+    }
+}
+
+/// Pools are equal only to their clones.
+impl PartialEq for Clients {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Clients {}
+
+/// Extracts the IP address and the TTL of its record.
+fn parse_dns_response(response: DnsResponse, method: QueryMethod) -> Result<(IpAddr, u32), Error> {
     let answer = match response.into_message().take_answers().into_iter().next() {
         Some(answer) => answer,
         None => return Err(Error::Addr),
     };
 
-    match answer.into_data() {
-        Some(RData::A(addr)) if method == QueryMethod::A => Ok(IpAddr::V4(addr.0)),
-        Some(RData::AAAA(addr)) if method == QueryMethod::AAAA => Ok(IpAddr::V6(addr.0)),
+    let ttl = answer.ttl();
+    let addr = match answer.into_data() {
+        Some(RData::A(addr)) if method == QueryMethod::A => IpAddr::V4(addr.0),
+        Some(RData::AAAA(addr)) if method == QueryMethod::AAAA => IpAddr::V6(addr.0),
         Some(RData::TXT(txt)) if method == QueryMethod::TXT => {
             let Some(addr_bytes) = txt.iter().next() else {
                 return Err(Error::Addr);
@@ -229,8 +1051,402 @@ fn parse_dns_response(response: DnsResponse, method: QueryMethod) -> Result<IpAd
                 return Err(Error::Addr);
             };
 
-            addr.parse().map_err(|_| Error::Addr)
+            addr.parse().map_err(|_| Error::Addr)?
         }
-        _ => Err(ProtoError::from(ProtoErrorKind::Message("invalid response")).into()),
+        _ => return Err(ProtoError::from(ProtoErrorKind::Message("invalid response")).into()),
+    };
+
+    Ok((addr, ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Behavior};
+
+    const PUBLIC: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+
+    #[tokio::test]
+    async fn tries_every_server_in_order() {
+        let resolver =
+            mock::resolver(&[Behavior::Silent, Behavior::Empty, Behavior::Answer(PUBLIC)]);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), 3);
+        let server = |i: usize| Some(SocketAddr::new(resolver.servers()[i], resolver.port()));
+        assert!(
+            matches!(&results[0], Err(f) if matches!(f.error, Error::Timeout) && f.server == server(0))
+        );
+        assert!(
+            matches!(&results[1], Err(f) if matches!(f.error, Error::Addr) && f.server == server(1))
+        );
+        assert_eq!(results[2].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn yields_each_answer() {
+        let other = Ipv4Addr::new(203, 0, 113, 8);
+        let resolver = mock::resolver(&[Behavior::Answer(other), Behavior::Answer(PUBLIC)]);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .map(|res| res.unwrap().addr)
+            .collect()
+            .await;
+
+        assert_eq!(results, [IpAddr::V4(other), IpAddr::V4(PUBLIC)]);
+    }
+
+    #[tokio::test]
+    async fn skips_servers_of_other_version() {
+        let resolver = mock::resolver(&[Behavior::Answer(PUBLIC)]);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V6, &Options::new())
+            .collect()
+            .await;
+
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_working_server() {
+        let resolver = mock::resolver(&[Behavior::Silent, Behavior::Answer(PUBLIC)]);
+
+        let addr = crate::resolve(AddrVersion::V4, &[(&resolver).into()], &Options::new())
+            .await
+            .unwrap();
+        assert_eq!(addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn retries_failed_queries() {
+        let resolver = mock::resolver(&[Behavior::Flaky {
+            drops: 2,
+            addr: PUBLIC,
+        }]);
+
+        let options = Options::new()
+            .with_retries(2)
+            .with_backoff(Duration::from_millis(10));
+        let results: Vec<_> = resolver.resolve(AddrVersion::V4, &options).collect().await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn options_override_resolver_timeout() {
+        let resolver = mock::resolver(&[Behavior::Silent]).with_timeout(Duration::from_secs(5));
+
+        let options = Options::new().with_timeout(Duration::from_millis(50));
+        let started_at = std::time::Instant::now();
+        let results: Vec<_> = resolver.resolve(AddrVersion::V4, &options).collect().await;
+
+        assert!(matches!(&results[..], [Err(f)] if matches!(f.error, Error::Timeout)));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn reports_resolution_details() {
+        let resolver = mock::resolver(&[Behavior::Empty, Behavior::Answer(PUBLIC)]);
+        let server = SocketAddr::new(resolver.servers()[1], resolver.port());
+
+        let resolution =
+            crate::resolve_detailed(AddrVersion::V4, &[(&resolver).into()], &Options::new())
+                .await
+                .unwrap();
+
+        assert_eq!(resolution.addr, IpAddr::V4(PUBLIC));
+        assert_eq!(resolution.resolver, mock::NAME);
+        assert_eq!(resolution.server, server);
+        assert_eq!(resolution.method, Some(QueryMethod::A));
+        assert_eq!(resolution.ttl, Some(mock::TTL));
+        assert!(resolution.latency < mock::TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn retries_truncated_responses_over_tcp() {
+        let resolver = mock::resolver(&[Behavior::Truncated(PUBLIC)]);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn queries_over_tcp() {
+        let resolver = mock::resolver(&[Behavior::TcpOnly(PUBLIC)]);
+
+        let udp: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert!(matches!(&udp[..], [Err(f)] if matches!(f.error, Error::Timeout)));
+
+        let resolver = resolver.with_transport(Transport::Tcp);
+        let tcp: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(tcp.len(), 1);
+        assert_eq!(tcp[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[cfg(feature = "async-io")]
+    #[test]
+    fn resolves_outside_tokio_runtime() {
+        // NOTE: the mock servers need a runtime, unlike the queries which
+        // are polled by an executor unaware of Tokio
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let resolver = {
+            let _guard = runtime.enter();
+            mock::resolver(&[Behavior::Silent, Behavior::Truncated(PUBLIC)])
+        };
+
+        let options = Options::new()
+            .with_retries(1)
+            .with_backoff(Duration::from_millis(10));
+        let results: Vec<_> =
+            futures_executor::block_on(resolver.resolve(AddrVersion::V4, &options).collect());
+        assert_eq!(results.len(), 2);
+        assert!(matches!(&results[0], Err(f) if matches!(f.error, Error::Timeout)));
+        assert_eq!(results[1].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[cfg(feature = "doh")]
+    #[tokio::test]
+    async fn queries_over_https() {
+        let (server, tls) = mock::spawn_https_server(Behavior::Answer(PUBLIC)).await;
+        let resolver = Resolver::new(mock::NAME, vec![server.ip()], QueryMethod::A)
+            .with_port(server.port())
+            .with_transport(Transport::Https(tls))
+            .with_timeout(Duration::from_secs(1));
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[cfg(feature = "doh")]
+    #[tokio::test]
+    async fn rejects_untrusted_https_server() {
+        let (server, _) = mock::spawn_https_server(Behavior::Answer(PUBLIC)).await;
+        let resolver = Resolver::new(mock::NAME, vec![server.ip()], QueryMethod::A)
+            .with_port(server.port())
+            .with_transport(Transport::Https(TlsConfig::new(mock::TLS_SERVER_NAME)))
+            .with_timeout(Duration::from_secs(1));
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert!(matches!(&results[..], [Err(_)]));
+    }
+
+    #[cfg(feature = "dot")]
+    #[tokio::test]
+    async fn queries_over_tls() {
+        let (server, tls) = mock::spawn_tls_server(Behavior::Answer(PUBLIC)).await;
+        let resolver = Resolver::new(mock::NAME, vec![server.ip()], QueryMethod::A)
+            .with_port(server.port())
+            .with_transport(Transport::Tls(tls))
+            .with_timeout(Duration::from_secs(1));
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[cfg(feature = "dot")]
+    #[tokio::test]
+    async fn reports_tls_errors() {
+        let (server, _) = mock::spawn_tls_server(Behavior::Answer(PUBLIC)).await;
+        let resolver = Resolver::new(mock::NAME, vec![server.ip()], QueryMethod::A)
+            .with_port(server.port())
+            .with_transport(Transport::Tls(TlsConfig::new(mock::TLS_SERVER_NAME)))
+            .with_timeout(Duration::from_secs(1));
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert!(matches!(
+            &results[..],
+            [Err(Failure {
+                error: Error::Tls(_),
+                ..
+            })]
+        ));
+    }
+
+    #[tokio::test]
+    async fn queries_records_of_class() {
+        let (servers, port) = mock::spawn_servers(&[Behavior::Chaos(PUBLIC)]);
+        let resolver = Resolver::new(mock::NAME, servers, QueryMethod::TXT)
+            .with_port(port)
+            .with_timeout(mock::TIMEOUT);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert!(matches!(&results[..], [Err(f)] if matches!(f.error, Error::Addr)));
+
+        let resolver = resolver.with_class(QueryClass::CH);
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn resolves_nameservers_by_name() {
+        let (_, port) = mock::spawn_servers(&[Behavior::Answer(PUBLIC)]);
+        let resolver = Resolver::new(mock::NAME, &[][..], QueryMethod::A)
+            .with_nameservers(&["localhost"])
+            .with_port(port)
+            .with_timeout(mock::TIMEOUT);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        let resolution = results[0].as_ref().unwrap();
+        assert_eq!(resolution.addr, IpAddr::V4(PUBLIC));
+        assert_eq!(
+            resolution.server,
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_server_addresses_before_nameservers() {
+        // NOTE: "localhost" resolves to the first server, which has no answer
+        let (servers, port) = mock::spawn_servers(&[Behavior::Empty, Behavior::Answer(PUBLIC)]);
+        let resolver = Resolver::new(mock::NAME, &servers[1..], QueryMethod::A)
+            .with_nameservers(&["localhost"])
+            .with_port(port)
+            .with_timeout(mock::TIMEOUT);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+
+        let results: Vec<_> = resolver
+            .with_nameserver_lookup(NameserverLookup::First)
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert!(
+            matches!(&results[..], [Err(f)] if f.server == Some(SocketAddr::new(servers[0], port)))
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_nameservers_if_server_addresses_fail() {
+        let (servers, port) = mock::spawn_servers(&[Behavior::Answer(PUBLIC), Behavior::Empty]);
+        let resolver = Resolver::new(mock::NAME, &servers[1..], QueryMethod::A)
+            .with_nameservers(&["localhost"])
+            .with_port(port)
+            .with_timeout(mock::TIMEOUT);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_server_addresses() {
+        let resolver = mock::resolver(&[Behavior::Answer(PUBLIC)])
+            .with_nameservers(&["nameserver.invalid"])
+            .with_nameserver_lookup(NameserverLookup::First);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+
+        let resolver = Resolver::new(mock::NAME, &[][..], QueryMethod::A)
+            .with_nameservers(&["nameserver.invalid"]);
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert!(matches!(&results[..], [Err(f)] if f.server.is_none()));
+    }
+
+    #[tokio::test]
+    async fn caches_nameserver_addresses() {
+        let (_, port) = mock::spawn_servers(&[Behavior::Answer(PUBLIC)]);
+        nameservers_cache().lock().unwrap().remove("localhost");
+
+        let resolver = Resolver::new(mock::NAME, &[][..], QueryMethod::A)
+            .with_nameservers(&["localhost"])
+            .with_port(port)
+            .with_timeout(mock::TIMEOUT);
+
+        let results: Vec<_> = resolver
+            .resolve(AddrVersion::V4, &Options::new())
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+
+        let cached = cached_nameserver("localhost").unwrap();
+        assert!(cached.contains(&IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+
+    #[tokio::test]
+    async fn binds_to_local_address() {
+        let local = Ipv4Addr::new(127, 0, 0, 9);
+        let options = Options::new().with_bind(Bind::Addr(IpAddr::V4(local)));
+
+        for transport in [Transport::Udp, Transport::Tcp] {
+            let resolver = mock::resolver(&[Behavior::Echo]).with_transport(transport);
+            let results: Vec<_> = resolver.resolve(AddrVersion::V4, &options).collect().await;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(local));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn binds_to_interface() {
+        let options = Options::new().with_bind(Bind::Interface("lo".to_owned()));
+
+        let resolver = mock::resolver(&[Behavior::Answer(PUBLIC)]);
+        let results: Vec<_> = resolver.resolve(AddrVersion::V4, &options).collect().await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().addr, IpAddr::V4(PUBLIC));
+
+        let options = Options::new().with_bind(Bind::Interface("missing0".to_owned()));
+        let results: Vec<_> = resolver.resolve(AddrVersion::V4, &options).collect().await;
+        assert!(matches!(&results[..], [Err(_)]));
     }
 }
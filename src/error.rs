@@ -1,3 +1,8 @@
+use std::fmt;
+use std::net::SocketAddr;
+
+use hickory_proto::error::{ProtoError, ProtoErrorKind};
+
 /// An error produced while attempting to resolve.
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -8,7 +13,151 @@ pub enum Error {
     /// IP version not requested was returned.
     #[error("IP version not requested was returned")]
     Version,
+    /// All queries failed.
+    #[error("all sources failed: {}", DisplayFailures(.0))]
+    All(Vec<Failure>),
+    /// Not enough sources agreed on the same IP address.
+    #[error("no consensus among sources (quorum {quorum}): {answers:?}")]
+    NoConsensus {
+        /// The number of sources which had to agree.
+        quorum: usize,
+        /// The address returned by each source which succeeded.
+        answers: Vec<(String, std::net::IpAddr)>,
+    },
+    /// I/O error.
+    #[error("io: {0}")]
+    Io(std::io::Error),
+    /// TLS error, e.g. the server certificate could not be verified.
+    #[cfg(any(feature = "http", feature = "doh", feature = "dot"))]
+    #[error("tls: {0}")]
+    Tls(rustls::Error),
+    /// HTTP request error.
+    #[cfg(feature = "http")]
+    #[error("http: {0}")]
+    Http(Box<dyn std::error::Error + Send + Sync>),
+    /// STUN server replied with an error code.
+    #[cfg(feature = "stun")]
+    #[error("stun server error {0}")]
+    Stun(u16),
+    /// Custom provider error.
+    #[error("provider: {0}")]
+    Provider(Box<dyn std::error::Error + Send + Sync>),
+    /// No response was received in time.
+    #[error("timed out")]
+    Timeout,
     /// DNS resolver error.
     #[error("dns resolver: {0}")]
-    Dns(#[from] hickory_client::proto::error::ProtoError),
+    Dns(ProtoError),
+}
+
+impl Error {
+    /// Returns an equal error to hand out the same outcome to several
+    /// callers. Wrapped errors which can't be cloned keep only their message.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Self::Addr => Self::Addr,
+            Self::Version => Self::Version,
+            Self::All(failures) => Self::All(failures.iter().map(Failure::duplicate).collect()),
+            Self::NoConsensus { quorum, answers } => Self::NoConsensus {
+                quorum: *quorum,
+                answers: answers.clone(),
+            },
+            Self::Io(err) => Self::Io(std::io::Error::new(err.kind(), err.to_string())),
+            #[cfg(any(feature = "http", feature = "doh", feature = "dot"))]
+            Self::Tls(err) => Self::Tls(err.clone()),
+            #[cfg(feature = "http")]
+            Self::Http(err) => Self::Http(err.to_string().into()),
+            #[cfg(feature = "stun")]
+            Self::Stun(code) => Self::Stun(*code),
+            Self::Provider(err) => Self::Provider(err.to_string().into()),
+            Self::Timeout => Self::Timeout,
+            Self::Dns(err) => Self::Dns(err.clone()),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        #[cfg(any(feature = "http", feature = "doh", feature = "dot"))]
+        if let Some(err) = tls_error(&err) {
+            return Self::Tls(err.clone());
+        }
+        Self::Io(err)
+    }
+}
+
+impl From<ProtoError> for Error {
+    fn from(err: ProtoError) -> Self {
+        #[cfg(any(feature = "http", feature = "doh", feature = "dot"))]
+        if let ProtoErrorKind::Io(io) = err.kind() {
+            if let Some(tls) = tls_error(io) {
+                return Self::Tls(tls.clone());
+            }
+        }
+
+        match err.kind() {
+            ProtoErrorKind::Timeout => Self::Timeout,
+            _ => Self::Dns(err),
+        }
+    }
+}
+
+/// Returns the TLS error wrapped by `tokio-rustls`, if any.
+#[cfg(any(feature = "http", feature = "doh", feature = "dot"))]
+fn tls_error(err: &std::io::Error) -> Option<&rustls::Error> {
+    err.get_ref()?.downcast_ref()
+}
+
+/// A failed query to one of the sources.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Failure {
+    /// The name of the resolver which was queried.
+    pub resolver: String,
+    /// The address of the queried server, if it was known.
+    pub server: Option<SocketAddr>,
+    /// The reason of the failure.
+    pub error: Error,
+}
+
+impl Failure {
+    /// Creates a failure of the `resolver`, optionally with the address of
+    /// the queried server.
+    #[must_use]
+    pub fn new(resolver: impl Into<String>, server: Option<SocketAddr>, error: Error) -> Self {
+        Self {
+            resolver: resolver.into(),
+            server,
+            error,
+        }
+    }
+}
+
+impl Failure {
+    pub(crate) fn duplicate(&self) -> Self {
+        Self::new(self.resolver.as_str(), self.server, self.error.duplicate())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.server {
+            Some(server) => write!(f, "{} ({server}): {}", self.resolver, self.error),
+            None => write!(f, "{}: {}", self.resolver, self.error),
+        }
+    }
+}
+
+struct DisplayFailures<'a>(&'a [Failure]);
+
+impl fmt::Display for DisplayFailures<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, failure) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            fmt::Display::fmt(failure, f)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,430 @@
+//! HTTP based resolvers.
+
+use std::borrow::Cow;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::body::Bytes;
+use hyper::header::{HOST, USER_AGENT};
+use hyper::{Request, Uri};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::error::{Error, Failure};
+use crate::options::{self, Options};
+use crate::{rt, tls};
+use crate::{AddrVersion, Bind, Resolution};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BODY_SIZE: usize = 1024;
+const USER_AGENT_VALUE: &str = concat!("getip/", env!("CARGO_PKG_VERSION"));
+
+/// All built-in HTTP resolvers.
+pub const ALL: &[&Resolver<'static>] = &[IPIFY, ICANHAZIP, IFCONFIG_CO];
+
+/// <https://www.ipify.org>
+pub const IPIFY: &Resolver<'static> =
+    &Resolver::new_static("https://api64.ipify.org/", ResponseFormat::Text);
+
+/// <https://icanhazip.com>
+pub const ICANHAZIP: &Resolver<'static> =
+    &Resolver::new_static("https://icanhazip.com/", ResponseFormat::Text);
+
+/// <https://ifconfig.co>
+pub const IFCONFIG_CO: &Resolver<'static> = &Resolver::new_static(
+    "https://ifconfig.co/json",
+    ResponseFormat::Json(Cow::Borrowed("ip")),
+);
+
+/// The format of the response body.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ResponseFormat<'r> {
+    /// The whole body is parsed as our IP address.
+    Text,
+    /// The body is a JSON object with our IP address in the specified field.
+    Json(Cow<'r, str>),
+}
+
+/// Options to build an HTTP resolver.
+#[derive(Debug)]
+pub struct Resolver<'r> {
+    url: Cow<'r, str>,
+    format: ResponseFormat<'r>,
+    timeout: Duration,
+}
+
+impl Resolver<'static> {
+    #[must_use]
+    const fn new_static(url: &'static str, format: ResponseFormat<'static>) -> Self {
+        Self {
+            url: Cow::Borrowed(url),
+            format,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl<'r> Resolver<'r> {
+    /// Creates a resolver which sends `GET` requests to the `url`.
+    ///
+    /// Both `http` and `https` URLs are supported. The request is sent
+    /// to each address of the host in turn.
+    #[must_use]
+    pub fn new<U>(url: U, format: ResponseFormat<'r>) -> Self
+    where
+        U: Into<Cow<'r, str>>,
+    {
+        Self {
+            url: url.into(),
+            format,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the time to wait for a response from each server.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the requested URL.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the format of the response body.
+    #[must_use]
+    pub fn format(&self) -> &ResponseFormat<'r> {
+        &self.format
+    }
+
+    /// Returns the time to wait for a response from each server.
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Queries the addresses of the host one by one, yielding the outcome
+    /// of each query.
+    ///
+    /// Only the addresses of the specified version are used, so that
+    /// the service sees our address of that version.
+    pub fn resolve(
+        &self,
+        version: AddrVersion,
+        options: &Options,
+    ) -> BoxStream<'static, Result<Resolution, Failure>> {
+        let target = match Target::parse(&self.url) {
+            Ok(target) => target,
+            Err(err) => {
+                let failure = Failure::new(self.url.as_ref(), None, err);
+                return Box::pin(stream::once(future::ready(Err(failure))));
+            }
+        };
+
+        let params = Arc::new(QueryParams {
+            target,
+            format: match &self.format {
+                ResponseFormat::Text => ResponseFormat::Text,
+                ResponseFormat::Json(field) => ResponseFormat::Json(Cow::Owned(field.to_string())),
+            },
+            timeout: options.timeout().unwrap_or(self.timeout),
+            bind: options.bind().cloned(),
+            retries: options.retries(),
+            backoff: options.backoff(),
+        });
+
+        let servers = {
+            let params = params.clone();
+            async move {
+                let target = &params.target;
+                let lookup = rt::lookup_host(&target.host, target.port);
+                match rt::timeout(params.timeout, lookup).await {
+                    Ok(Ok(servers)) => Ok(servers
+                        .into_iter()
+                        .filter(|server| version.matches(server.ip()))
+                        .collect::<Vec<_>>()),
+                    Ok(Err(err)) => Err(Failure::new(target.url.as_str(), None, err.into())),
+                    Err(_) => Err(Failure::new(target.url.as_str(), None, Error::Timeout)),
+                }
+            }
+        };
+
+        Box::pin(
+            stream::once(servers).flat_map(move |servers| match servers {
+                Ok(servers) => {
+                    let params = params.clone();
+                    stream::iter(servers)
+                        .then(move |server| resolve(server, params.clone()))
+                        .left_stream()
+                }
+                Err(failure) => stream::once(future::ready(Err(failure))).right_stream(),
+            }),
+        )
+    }
+}
+
+struct QueryParams {
+    target: Target,
+    format: ResponseFormat<'static>,
+    timeout: Duration,
+    bind: Option<Bind>,
+    retries: usize,
+    backoff: Duration,
+}
+
+async fn resolve(server: SocketAddr, params: Arc<QueryParams>) -> Result<Resolution, Failure> {
+    query(server, &params)
+        .await
+        .map_err(|error| Failure::new(params.target.url.as_str(), Some(server), error))
+}
+
+async fn query(server: SocketAddr, params: &QueryParams) -> Result<Resolution, Error> {
+    let started_at = Instant::now();
+    let body = options::retry(params.retries, params.backoff, || {
+        fetch(&params.target, server, params.timeout, params.bind.as_ref())
+    })
+    .await?;
+    let latency = started_at.elapsed();
+
+    Ok(Resolution {
+        addr: parse_body(&body, &params.format)?,
+        resolver: params.target.url.clone(),
+        server,
+        method: None,
+        latency,
+        ttl: None,
+        stale: None,
+    })
+}
+
+struct Target {
+    url: String,
+    secure: bool,
+    host: String,
+    port: u16,
+    uri: Uri,
+}
+
+impl Target {
+    fn parse(url: &str) -> Result<Self, Error> {
+        let uri = url.parse::<Uri>().map_err(|e| Error::Http(e.into()))?;
+
+        let secure = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(Error::Http("unsupported URL scheme".into())),
+        };
+
+        let Some(host) = uri.host() else {
+            return Err(Error::Http("no host in URL".into()));
+        };
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_owned();
+        let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+        Ok(Self {
+            url: url.to_owned(),
+            secure,
+            host,
+            port,
+            uri,
+        })
+    }
+}
+
+async fn fetch(
+    target: &Target,
+    server: SocketAddr,
+    timeout: Duration,
+    bind: Option<&Bind>,
+) -> Result<Bytes, Error> {
+    let fut = async {
+        let stream = crate::bind::tcp_connect(bind, server).await?;
+        if !target.secure {
+            return request(stream, target).await;
+        }
+
+        let server_name = rustls::ServerName::try_from(target.host.as_str())
+            .map_err(|e| Error::Http(e.into()))?;
+        let stream = tokio_rustls::TlsConnector::from(tls::default_client_config())
+            .connect(server_name, stream)
+            .await?;
+        request(stream, target).await
+    };
+
+    rt::timeout(timeout, fut)
+        .await
+        .map_err(|_| Error::Timeout)?
+}
+
+async fn request<S>(stream: S, target: &Target) -> Result<Bytes, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(http_error)?;
+
+    let request = Request::get(target.uri.path_and_query().map_or("/", |p| p.as_str()))
+        .header(HOST, target.uri.authority().map_or("", |a| a.as_str()))
+        .header(USER_AGENT, USER_AGENT_VALUE)
+        .body(Empty::<Bytes>::new())
+        .map_err(http_error)?;
+
+    let response = async move {
+        let response = sender.send_request(request).await.map_err(http_error)?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(Error::Http(format!("unexpected status {status}").into()));
+        }
+
+        let body = Limited::new(response.into_body(), MAX_BODY_SIZE);
+        Ok(body.collect().await.map_err(Error::Http)?.to_bytes())
+    };
+    futures_util::pin_mut!(response);
+
+    // NOTE: the connection is driven until the response body is received
+    match future::select(conn, response).await {
+        Either::Left((Ok(()), response)) => response.await,
+        Either::Left((Err(err), _)) => Err(http_error(err)),
+        Either::Right((res, _)) => res,
+    }
+}
+
+fn parse_body(body: &[u8], format: &ResponseFormat<'_>) -> Result<IpAddr, Error> {
+    match format {
+        ResponseFormat::Text => {
+            let Ok(addr) = std::str::from_utf8(body) else {
+                return Err(Error::Addr);
+            };
+            addr.trim().parse().map_err(|_| Error::Addr)
+        }
+        ResponseFormat::Json(field) => {
+            let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) else {
+                return Err(Error::Addr);
+            };
+            let Some(addr) = json.get(field.as_ref()).and_then(|addr| addr.as_str()) else {
+                return Err(Error::Addr);
+            };
+            addr.trim().parse().map_err(|_| Error::Addr)
+        }
+    }
+}
+
+fn http_error<E>(err: E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    Error::Http(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::Ipv4Addr;
+
+    use http_body_util::Full;
+    use hyper::service::service_fn;
+    use hyper::{Response, StatusCode};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::mock::{self, Behavior};
+    use crate::Source;
+
+    const PUBLIC: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+
+    /// Spawns an HTTP server which replies to every request with the
+    /// specified status and body.
+    async fn spawn_server(status: StatusCode, body: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = service_fn(move |_| async move {
+                    let mut response =
+                        Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+                    *response.status_mut() = status;
+                    Ok::<_, Infallible>(response)
+                });
+
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service),
+                );
+            }
+        });
+
+        addr
+    }
+
+    async fn resolve_first(resolver: &Resolver<'_>) -> Result<IpAddr, Error> {
+        resolver
+            .resolve(AddrVersion::Any, &Options::new())
+            .next()
+            .await
+            .unwrap()
+            .map(|resolution| resolution.addr)
+            .map_err(|failure| failure.error)
+    }
+
+    #[tokio::test]
+    async fn parses_text_response() {
+        let server = spawn_server(StatusCode::OK, "203.0.113.7\n").await;
+        let resolver = Resolver::new(format!("http://{server}/"), ResponseFormat::Text);
+
+        let addr = resolve_first(&resolver).await.unwrap();
+        assert_eq!(addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn parses_json_response() {
+        let server = spawn_server(StatusCode::OK, r#"{"ip":"203.0.113.7","country":"XX"}"#).await;
+        let resolver = Resolver::new(
+            format!("http://{server}/json"),
+            ResponseFormat::Json("ip".into()),
+        );
+
+        let addr = resolve_first(&resolver).await.unwrap();
+        assert_eq!(addr, IpAddr::V4(PUBLIC));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_responses() {
+        let server = spawn_server(StatusCode::OK, "<html></html>").await;
+        let resolver = Resolver::new(format!("http://{server}/"), ResponseFormat::Text);
+        assert!(matches!(resolve_first(&resolver).await, Err(Error::Addr)));
+
+        let server = spawn_server(StatusCode::TOO_MANY_REQUESTS, "203.0.113.7").await;
+        let resolver = Resolver::new(format!("http://{server}/"), ResponseFormat::Text);
+        assert!(matches!(
+            resolve_first(&resolver).await,
+            Err(Error::Http(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn mixes_with_dns_resolvers() {
+        let dns = mock::resolver(&[Behavior::Empty]);
+        let server = spawn_server(StatusCode::OK, "203.0.113.7").await;
+        let http = Resolver::new(format!("http://{server}/"), ResponseFormat::Text);
+
+        let sources = [Source::from(&dns), Source::from(&http)];
+        let addr = crate::resolve(AddrVersion::V4, &sources, &Options::new())
+            .await
+            .unwrap();
+        assert_eq!(addr, IpAddr::V4(PUBLIC));
+    }
+}
@@ -0,0 +1,91 @@
+//! Public IP addresses of the local network interfaces.
+
+use std::net::IpAddr;
+
+use futures_util::future;
+
+use crate::{AddrVersion, Bind, Error, Options, Source};
+
+/// The public IP address observed from an address of a local network interface.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct InterfaceAddr {
+    /// The name of the interface.
+    pub name: String,
+    /// The address assigned to the interface.
+    pub local: IpAddr,
+    /// The public address which the sources saw the queries from.
+    pub public: Result<IpAddr, Error>,
+}
+
+impl InterfaceAddr {
+    /// Returns `true` if the traffic of the interface is translated (NAT),
+    /// `false` if its address is directly public or `None` if the public
+    /// address was not resolved.
+    #[must_use]
+    pub fn is_nat(&self) -> Option<bool> {
+        match &self.public {
+            Ok(public) => Some(*public != self.local),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Resolves the public IP address observed from each address of the local
+/// network interfaces concurrently, querying `sources` according to the
+/// `options`.
+///
+/// Loopback and link-local addresses are skipped. The queries of each
+/// address are sent from it, regardless of [`Options::bind`].
+pub async fn resolve(
+    sources: &[Source<'_>],
+    options: &Options,
+) -> Result<Vec<InterfaceAddr>, Error> {
+    let interfaces = if_addrs::get_if_addrs()?;
+
+    let lookups = interfaces
+        .into_iter()
+        .filter(|interface| !interface.is_loopback() && !interface.is_link_local())
+        .map(|interface| async move {
+            let local = interface.ip();
+            let version = match local {
+                IpAddr::V4(_) => AddrVersion::V4,
+                IpAddr::V6(_) => AddrVersion::V6,
+            };
+            let options = options.clone().with_bind(Bind::Addr(local));
+
+            InterfaceAddr {
+                name: interface.name,
+                local,
+                public: crate::resolve(version, sources, &options).await,
+            }
+        });
+
+    Ok(future::join_all(lookups).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Behavior};
+
+    #[tokio::test]
+    async fn resolves_each_interface() {
+        let resolver = mock::resolver(&[Behavior::Echo]);
+
+        let addrs = resolve(&[(&resolver).into()], &Options::new())
+            .await
+            .unwrap();
+        for addr in addrs {
+            assert!(!addr.local.is_loopback());
+            // NOTE: the mock servers are IPv4 only and see the local address
+            match addr.local {
+                IpAddr::V4(_) => {
+                    assert_eq!(addr.public.as_ref().unwrap(), &addr.local);
+                    assert_eq!(addr.is_nat(), Some(false));
+                }
+                IpAddr::V6(_) => assert_eq!(addr.is_nat(), None),
+            }
+        }
+    }
+}
@@ -1,11 +1,48 @@
+#[cfg(feature = "stun")]
+use std::net::SocketAddr;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
+use futures_util::future;
+#[cfg(feature = "stun")]
 use futures_util::StreamExt;
 
-pub use self::error::Error;
+pub use self::addrs::Addrs;
+pub use self::bind::Bind;
+pub use self::cache::Cache;
+pub use self::client::{GetIp, GetIpBuilder};
+pub use self::error::{Error, Failure};
+pub use self::options::Options;
+pub use self::provider::Provider;
+pub use self::resolution::Resolution;
+pub use self::source::Source;
+pub use self::store::Store;
+pub use self::strategy::Strategy;
+pub use self::watch::{watch, Change, Watcher};
 
-mod dns;
+mod addrs;
+mod bind;
+mod cache;
+mod client;
+pub mod dns;
 mod error;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "interfaces")]
+pub mod interfaces;
+#[cfg(test)]
+mod mock;
+mod options;
+mod provider;
+mod resolution;
+mod rt;
+mod source;
+mod store;
+mod strategy;
+#[cfg(feature = "stun")]
+pub mod stun;
+#[cfg(any(feature = "http", feature = "doh", feature = "dot"))]
+mod tls;
+mod watch;
 
 /// The version of IP address to resolve.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -29,48 +66,310 @@ impl AddrVersion {
     }
 }
 
+/// Sources used by [`addr`], [`addr_v4`], [`addr_v6`] and [`addrs`]:
+/// all of the built-in DNS resolvers, see [`dns::ALL`].
+pub const DEFAULT: &[Source<'static>] = &[
+    Source::Dns(dns::OPENDNS_V4),
+    Source::Dns(dns::OPENDNS_V6),
+    Source::Dns(dns::GOOGLE_V4),
+    Source::Dns(dns::GOOGLE_V6),
+    Source::Dns(dns::CLOUDFLARE_V4),
+    Source::Dns(dns::CLOUDFLARE_V6),
+    Source::Dns(dns::AKAMAI_V4),
+    Source::Dns(dns::AKAMAI_V6),
+];
+
+/// Resolves the public IP address of any version using the built-in resolvers.
 pub async fn addr() -> Result<IpAddr, Error> {
-    resolve(AddrVersion::Any).await
+    resolve(AddrVersion::Any, DEFAULT, &Options::new()).await
 }
 
+/// Resolves the public IPv4 address using the built-in resolvers.
 pub async fn addr_v4() -> Result<Ipv4Addr, Error> {
-    Ok(match resolve(AddrVersion::V4).await? {
-        IpAddr::V4(addr) => addr,
-        IpAddr::V6(_) => unreachable!(),
-    })
+    Ok(
+        match resolve(AddrVersion::V4, DEFAULT, &Options::new()).await? {
+            IpAddr::V4(addr) => addr,
+            IpAddr::V6(_) => unreachable!(),
+        },
+    )
 }
 
+/// Resolves the public IPv6 address using the built-in resolvers.
 pub async fn addr_v6() -> Result<Ipv6Addr, Error> {
-    Ok(match resolve(AddrVersion::V6).await? {
-        IpAddr::V4(_) => unreachable!(),
-        IpAddr::V6(addr) => addr,
-    })
+    Ok(
+        match resolve(AddrVersion::V6, DEFAULT, &Options::new()).await? {
+            IpAddr::V4(_) => unreachable!(),
+            IpAddr::V6(addr) => addr,
+        },
+    )
+}
+
+/// Resolves the public IPv4 and IPv6 addresses concurrently using the
+/// built-in resolvers.
+pub async fn addrs() -> Addrs {
+    resolve_addrs(DEFAULT, &Options::new()).await
+}
+
+/// Resolves the public IPv4 and IPv6 addresses concurrently, querying
+/// `sources` for each version according to the `options`.
+pub async fn resolve_addrs(sources: &[Source<'_>], options: &Options) -> Addrs {
+    let (v4, v6) = future::join(
+        resolve(AddrVersion::V4, sources, options),
+        resolve(AddrVersion::V6, sources, options),
+    )
+    .await;
+
+    Addrs::from_results(v4, v6)
+}
+
+/// Resolves the public IP address observed from each address of the local
+/// network interfaces using the built-in resolvers.
+///
+/// See [`interfaces::resolve`] for details.
+#[cfg(feature = "interfaces")]
+pub async fn interface_addrs() -> Result<Vec<interfaces::InterfaceAddr>, Error> {
+    interfaces::resolve(DEFAULT, &Options::new()).await
 }
 
-pub async fn resolve(version: AddrVersion) -> Result<IpAddr, Error> {
-    let mut last_err = Error::Addr;
+/// Resolves the public address and port mapped to a fresh UDP socket
+/// using the built-in STUN resolvers.
+///
+/// See [`stun::binding_request`] to resolve the mapping of an existing socket.
+#[cfg(feature = "stun")]
+pub async fn mapped_addr() -> Result<SocketAddr, Error> {
+    let mut failures = Vec::new();
 
-    for resolver in dns::ALL {
-        let mut stream = resolver.resolve(version);
+    for resolver in stun::ALL {
+        let mut stream = resolver.mapped_addrs(AddrVersion::Any, &Options::new());
         while let Some(res) = stream.next().await {
             match res {
-                Ok(addr) if version.matches(addr) => return Ok(addr),
-                Ok(_) => return Err(Error::Version),
-                Err(err) => last_err = err,
+                Ok(addr) => return Ok(addr),
+                Err(failure) => failures.push(failure),
             }
         }
     }
 
-    Err(last_err)
+    Err(strategy::all_failed(failures))
+}
+
+/// Resolves the public IP address of the specified version, querying
+/// `sources` according to the `options`.
+///
+/// Note that a last known address returned from the [`Store`] can't be
+/// told apart from a fresh one, use [`resolve_detailed`] to do so.
+pub async fn resolve(
+    version: AddrVersion,
+    sources: &[Source<'_>],
+    options: &Options,
+) -> Result<IpAddr, Error> {
+    resolve_detailed(version, sources, options)
+        .await
+        .map(|resolution| resolution.addr)
+}
+
+/// Same as [`resolve`], but also returns the details of how the address
+/// was resolved.
+///
+/// If all sources fail and the `options` have a [`Store`], the last known
+/// address is returned instead, marked as [stale](Resolution::stale).
+/// Answers which disagree or are of the wrong version are not failures
+/// of the sources, so the error is returned in that case.
+pub async fn resolve_detailed(
+    version: AddrVersion,
+    sources: &[Source<'_>],
+    options: &Options,
+) -> Result<Resolution, Error> {
+    let resolution = async {
+        match options.strategy() {
+            Strategy::Sequential => strategy::sequential(version, sources, options).await,
+            Strategy::Race => strategy::race(version, sources, options).await,
+            Strategy::Consensus { quorum } => {
+                strategy::consensus(version, sources, options, quorum).await
+            }
+        }
+    };
+
+    let res = match options.deadline() {
+        Some(deadline) => rt::timeout(deadline, resolution)
+            .await
+            .unwrap_or(Err(Error::Timeout)),
+        None => resolution.await,
+    };
+
+    let Some(store) = options.store() else {
+        return res;
+    };
+    match res {
+        Ok(resolution) => {
+            // NOTE: the address is persisted in the background, failing to do
+            // so must not fail the resolution
+            drop(store.save(version, &resolution));
+            Ok(resolution)
+        }
+        Err(err @ (Error::All(_) | Error::Addr | Error::Timeout)) => {
+            match store.load(version).await {
+                Ok(Some(resolution)) => Ok(resolution),
+                _ => Err(err),
+            }
+        }
+        Err(err) => Err(err),
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::net::SocketAddr;
+
     use super::*;
+    use crate::mock::{self, Behavior};
+
+    const PUBLIC: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+
+    /// Waits for the address of the `version` to be saved in the background.
+    async fn stored(store: &Store, version: AddrVersion) {
+        for _ in 0..100 {
+            if store.load(version).await.unwrap().is_some() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("the address was not stored");
+    }
 
     #[tokio::test]
     async fn resolve_my() {
         let public = addr().await.unwrap();
         println!("{public:?}");
     }
+
+    #[test]
+    fn default_sources_include_all_dns_resolvers() {
+        for resolver in dns::ALL {
+            assert!(DEFAULT.iter().any(|source| match source {
+                Source::Dns(default) => {
+                    default.name() == resolver.name()
+                        && default.servers() == resolver.servers()
+                        && default.nameservers() == resolver.nameservers()
+                        && default.method() == resolver.method()
+                }
+                _ => false,
+            }));
+        }
+    }
+
+    #[tokio::test]
+    async fn deadline_limits_resolution() {
+        let resolver = mock::resolver(&[Behavior::Silent, Behavior::Silent]);
+
+        let options = Options::new().with_deadline(std::time::Duration::from_millis(300));
+        let res = resolve(AddrVersion::V4, &[(&resolver).into()], &options).await;
+        assert!(matches!(res, Err(Error::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn aggregates_all_failures() {
+        let first = mock::resolver(&[Behavior::Silent, Behavior::Empty]);
+        let second = mock::resolver(&[Behavior::Empty]);
+
+        let sources = [Source::from(&first), Source::from(&second)];
+        let res = resolve(AddrVersion::V4, &sources, &Options::new()).await;
+        let Err(Error::All(failures)) = res else {
+            panic!("unexpected result: {res:?}");
+        };
+
+        let servers = failures.iter().map(|f| f.server).collect::<Vec<_>>();
+        assert_eq!(
+            servers,
+            [
+                Some(SocketAddr::new(first.servers()[0], first.port())),
+                Some(SocketAddr::new(first.servers()[1], first.port())),
+                Some(SocketAddr::new(second.servers()[0], second.port())),
+            ]
+        );
+        assert!(matches!(failures[0].error, Error::Timeout));
+        assert!(matches!(failures[1].error, Error::Addr));
+        assert!(matches!(failures[2].error, Error::Addr));
+    }
+
+    #[tokio::test]
+    async fn resolves_both_versions() {
+        let resolver = mock::resolver(&[Behavior::Silent, Behavior::Answer(PUBLIC)]);
+
+        let addrs = resolve_addrs(&[(&resolver).into()], &Options::new()).await;
+        assert_eq!(addrs.v4, Some(PUBLIC));
+        assert!(addrs.v4_error.is_none());
+        // NOTE: the mock servers are IPv4 only
+        assert_eq!(addrs.v6, None);
+        assert!(matches!(addrs.v6_error, Some(Error::Addr)));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_stored_address() {
+        let path = std::env::temp_dir().join(format!("getip-{}.store", std::process::id()));
+        let options = Options::new().with_store(Store::new(&path));
+
+        let resolver = mock::resolver(&[Behavior::Answer(PUBLIC)]);
+        let fresh = resolve_detailed(AddrVersion::V4, &[(&resolver).into()], &options)
+            .await
+            .unwrap();
+        assert_eq!(fresh.stale, None);
+        stored(options.store().unwrap(), AddrVersion::V4).await;
+
+        let resolver = mock::resolver(&[Behavior::Empty]);
+        let stale = resolve_detailed(AddrVersion::V4, &[(&resolver).into()], &options)
+            .await
+            .unwrap();
+        assert_eq!(stale.addr, IpAddr::V4(PUBLIC));
+        assert_eq!(stale.server, fresh.server);
+        assert!(stale.stale.unwrap() < std::time::Duration::from_secs(5));
+
+        let res = resolve_detailed(AddrVersion::V6, &[(&resolver).into()], &options).await;
+        assert!(matches!(res, Err(Error::Addr)));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_disagreement_despite_stored_address() {
+        let path = std::env::temp_dir().join(format!("getip-{}.consensus", std::process::id()));
+        let options = Options::new().with_store(Store::new(&path));
+
+        let resolver = mock::resolver(&[Behavior::Answer(PUBLIC)]);
+        resolve(AddrVersion::V4, &[(&resolver).into()], &options)
+            .await
+            .unwrap();
+        stored(options.store().unwrap(), AddrVersion::V4).await;
+
+        let spoofed = mock::resolver(&[Behavior::Answer(Ipv4Addr::new(198, 51, 100, 1))]);
+        let sources = [Source::from(&resolver), Source::from(&spoofed)];
+        let options = options.with_strategy(Strategy::Consensus { quorum: 2 });
+        let res = resolve_detailed(AddrVersion::V4, &sources, &options).await;
+        assert!(matches!(res, Err(Error::NoConsensus { .. })), "{res:?}");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_stored_address_without_consensus_answers() {
+        let path = std::env::temp_dir().join(format!("getip-{}.offline", std::process::id()));
+        let options = Options::new().with_store(Store::new(&path));
+
+        let resolver = mock::resolver(&[Behavior::Answer(PUBLIC)]);
+        resolve(AddrVersion::V4, &[(&resolver).into()], &options)
+            .await
+            .unwrap();
+        stored(options.store().unwrap(), AddrVersion::V4).await;
+
+        let first = mock::resolver(&[Behavior::Empty]);
+        let second = mock::resolver(&[Behavior::Silent]);
+        let sources = [Source::from(&first), Source::from(&second)];
+        let options = options.with_strategy(Strategy::Consensus { quorum: 2 });
+        let stale = resolve_detailed(AddrVersion::V4, &sources, &options)
+            .await
+            .unwrap();
+        assert_eq!(stale.addr, IpAddr::V4(PUBLIC));
+        assert!(stale.stale.is_some());
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,256 @@
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+use getip::{dns, AddrVersion, Bind, Error, Options, Resolution, Source, Store, Strategy};
+
+/// Find the public IP address of this device.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Resolve only the IPv4 address.
+    #[arg(short = '4', long = "ipv4", conflicts_with = "v6")]
+    v4: bool,
+
+    /// Resolve only the IPv6 address.
+    #[arg(short = '6', long = "ipv6")]
+    v6: bool,
+
+    /// Providers to query, in order. All DNS providers by default.
+    #[arg(short, long = "provider", value_enum, value_delimiter = ',')]
+    providers: Vec<Provider>,
+
+    /// Time to wait for a response from each server, in seconds.
+    #[arg(short, long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Maximum time the whole resolution may take, in seconds.
+    #[arg(short, long, value_parser = parse_duration)]
+    deadline: Option<Duration>,
+
+    /// The way providers are queried.
+    #[arg(short, long, value_enum, default_value_t = StrategyArg::Sequential)]
+    strategy: StrategyArg,
+
+    /// The number of providers which must agree with the consensus strategy.
+    #[arg(short, long, default_value_t = 2)]
+    quorum: usize,
+
+    /// Local address or network interface to send queries from.
+    #[arg(short, long, value_parser = parse_bind)]
+    bind: Option<Bind>,
+
+    /// File to keep the last known address in, used when all providers fail.
+    #[arg(long)]
+    store: Option<std::path::PathBuf>,
+
+    /// Output format.
+    #[arg(short, long, value_enum, default_value_t = Format::Plain)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Provider {
+    Opendns,
+    Google,
+    Cloudflare,
+    Akamai,
+    #[cfg(feature = "http")]
+    Ipify,
+    #[cfg(feature = "http")]
+    Icanhazip,
+    #[cfg(feature = "http")]
+    IfconfigCo,
+    #[cfg(feature = "stun")]
+    StunGoogle,
+    #[cfg(feature = "stun")]
+    StunCloudflare,
+}
+
+impl Provider {
+    fn sources(self) -> Vec<Source<'static>> {
+        match self {
+            Self::Opendns => vec![Source::Dns(dns::OPENDNS_V4), Source::Dns(dns::OPENDNS_V6)],
+            Self::Google => vec![Source::Dns(dns::GOOGLE_V4), Source::Dns(dns::GOOGLE_V6)],
+            Self::Cloudflare => vec![
+                Source::Dns(dns::CLOUDFLARE_V4),
+                Source::Dns(dns::CLOUDFLARE_V6),
+            ],
+            Self::Akamai => vec![Source::Dns(dns::AKAMAI_V4), Source::Dns(dns::AKAMAI_V6)],
+            #[cfg(feature = "http")]
+            Self::Ipify => vec![Source::Http(getip::http::IPIFY)],
+            #[cfg(feature = "http")]
+            Self::Icanhazip => vec![Source::Http(getip::http::ICANHAZIP)],
+            #[cfg(feature = "http")]
+            Self::IfconfigCo => vec![Source::Http(getip::http::IFCONFIG_CO)],
+            #[cfg(feature = "stun")]
+            Self::StunGoogle => vec![Source::Stun(getip::stun::GOOGLE)],
+            #[cfg(feature = "stun")]
+            Self::StunCloudflare => vec![Source::Stun(getip::stun::CLOUDFLARE)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum StrategyArg {
+    Sequential,
+    Race,
+    Consensus,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Plain,
+    Json,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let version = match (args.v4, args.v6) {
+        (true, _) => AddrVersion::V4,
+        (_, true) => AddrVersion::V6,
+        _ => AddrVersion::Any,
+    };
+
+    let sources = if args.providers.is_empty() {
+        getip::DEFAULT.to_vec()
+    } else {
+        args.providers.iter().flat_map(|p| p.sources()).collect()
+    };
+
+    let mut options = Options::new().with_strategy(match args.strategy {
+        StrategyArg::Sequential => Strategy::Sequential,
+        StrategyArg::Race => Strategy::Race,
+        StrategyArg::Consensus => Strategy::Consensus {
+            quorum: args.quorum,
+        },
+    });
+    if let Some(timeout) = args.timeout {
+        options = options.with_timeout(timeout);
+    }
+    if let Some(deadline) = args.deadline {
+        options = options.with_deadline(deadline);
+    }
+    if let Some(bind) = args.bind {
+        options = options.with_bind(bind);
+    }
+    if let Some(store) = args.store {
+        options = options.with_store(Store::new(store));
+    }
+
+    let res = getip::resolve_detailed(version, &sources, &options).await;
+    match (args.format, &res) {
+        (Format::Plain, Ok(resolution)) => println!("{}", resolution.addr),
+        (Format::Plain, Err(err)) => eprintln!("error: {err}"),
+        (Format::Json, Ok(resolution)) => println!("{}", resolution_json(resolution)),
+        (Format::Json, Err(err)) => println!("{}", serde_json::json!({ "error": err.to_string() })),
+    }
+
+    match res {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => ExitCode::from(exit_code(&err)),
+    }
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|_| "expected a number of seconds")?;
+    Duration::try_from_secs_f64(secs).map_err(|err| err.to_string())
+}
+
+fn parse_bind(value: &str) -> Result<Bind, String> {
+    if let Ok(addr) = value.parse() {
+        return Ok(Bind::Addr(addr));
+    }
+
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    return Ok(Bind::Interface(value.to_owned()));
+
+    #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+    Err("expected an IP address".to_owned())
+}
+
+fn resolution_json(resolution: &Resolution) -> serde_json::Value {
+    serde_json::json!({
+        "addr": resolution.addr.to_string(),
+        "resolver": resolution.resolver,
+        "server": resolution.server.to_string(),
+        "method": resolution.method.map(|method| format!("{method:?}")),
+        "latency_ms": resolution.latency.as_millis() as u64,
+        "ttl": resolution.ttl.map(|ttl| ttl.as_secs()),
+        "stale": resolution.stale.map(|age| age.as_secs()),
+    })
+}
+
+/// Maps errors to exit codes. `2` is used by clap for invalid arguments.
+fn exit_code(err: &Error) -> u8 {
+    match err {
+        Error::Addr | Error::Version => 3,
+        Error::Timeout => 4,
+        Error::NoConsensus { .. } => 5,
+        Error::Dns(_) | Error::Io(_) | Error::Provider(_) => 6,
+        #[cfg(feature = "http")]
+        Error::Http(_) => 6,
+        #[cfg(feature = "stun")]
+        Error::Stun(_) => 6,
+        #[cfg(any(feature = "http", feature = "doh", feature = "dot"))]
+        Error::Tls(_) => 6,
+        Error::All(failures) => {
+            // NOTE: the code of the failures is used only if they agree
+            let mut codes = failures.iter().map(|failure| exit_code(&failure.error));
+            match codes.next() {
+                Some(code) if codes.all(|other| other == code) => code,
+                _ => 1,
+            }
+        }
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use getip::Failure;
+
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+        for value in ["-1", "nan", "inf", "1e30", "soon"] {
+            assert!(parse_duration(value).is_err(), "{value}");
+        }
+
+        let err = Args::try_parse_from(["getip", "--timeout=-1"]).unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[test]
+    fn maps_errors_to_exit_codes() {
+        assert_eq!(exit_code(&Error::Addr), 3);
+        assert_eq!(exit_code(&Error::Version), 3);
+        assert_eq!(exit_code(&Error::Timeout), 4);
+        let no_consensus = Error::NoConsensus {
+            quorum: 2,
+            answers: Vec::new(),
+        };
+        assert_eq!(exit_code(&no_consensus), 5);
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert_eq!(exit_code(&Error::Io(io)), 6);
+        assert_eq!(exit_code(&Error::Provider("down".into())), 6);
+    }
+
+    #[test]
+    fn keeps_code_of_agreeing_failures() {
+        let failure = |error| Failure::new("resolver", None, error);
+
+        let timeouts = Error::All(vec![failure(Error::Timeout), failure(Error::Timeout)]);
+        assert_eq!(exit_code(&timeouts), 4);
+
+        let mixed = Error::All(vec![failure(Error::Timeout), failure(Error::Addr)]);
+        assert_eq!(exit_code(&mixed), 1);
+
+        assert_eq!(exit_code(&Error::All(Vec::new())), 1);
+    }
+}
@@ -0,0 +1,326 @@
+//! Local DNS servers for tests.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hickory_proto::op::{Message, MessageType};
+use hickory_proto::rr::rdata::{A, TXT};
+use hickory_proto::rr::{DNSClass, RData, Record};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+
+use crate::dns::{QueryMethod, Resolver};
+
+pub const NAME: &str = "myip.example.com";
+pub const TIMEOUT: Duration = Duration::from_millis(200);
+pub const TTL: Duration = Duration::from_secs(60);
+#[cfg(any(feature = "doh", feature = "dot"))]
+pub const TLS_SERVER_NAME: &str = "dns.example.com";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Behavior {
+    /// Replies with an `A` record with the specified address.
+    Answer(Ipv4Addr),
+    /// Replies without any answers.
+    Empty,
+    /// Ignores the first `drops` queries, then replies like `Answer`.
+    Flaky { drops: usize, addr: Ipv4Addr },
+    /// Replies to each query with the next address, repeating the last one.
+    /// `None` replies without any answers.
+    Sequence(&'static [Option<Ipv4Addr>]),
+    /// Replies with a truncated response over UDP and like `Answer` over TCP.
+    Truncated(Ipv4Addr),
+    /// Never replies over UDP and replies like `Answer` over TCP.
+    TcpOnly(Ipv4Addr),
+    /// Replies to `CH` class queries with a `TXT` record containing the
+    /// specified address and to other queries without any answers.
+    Chaos(Ipv4Addr),
+    /// Replies with an `A` record with the address the query came from.
+    Echo,
+    /// Never replies over UDP and replies over TCP with an `A` record
+    /// encoding the port the query came from.
+    Port,
+    /// Replies like `Answer` and closes stream connections shortly after
+    /// each reply, like servers do with idle connections.
+    Closing(Ipv4Addr),
+    /// Replies like `Answer` to the first query over each stream connection
+    /// and ignores the following ones, keeping the connection open.
+    Stalling(Ipv4Addr),
+    /// Never replies.
+    Silent,
+}
+
+/// Creates a resolver with a mock server for each behavior.
+pub fn resolver(behaviors: &[Behavior]) -> Resolver<'static> {
+    let (servers, port) = spawn_servers(behaviors);
+    Resolver::new(NAME, servers, QueryMethod::A)
+        .with_port(port)
+        .with_timeout(TIMEOUT)
+}
+
+/// Spawns a mock DNS server for each behavior on a separate loopback
+/// address, all of them sharing the same port. Each server listens on
+/// both UDP and TCP.
+pub fn spawn_servers(behaviors: &[Behavior]) -> (Vec<IpAddr>, u16) {
+    let mut servers = Vec::new();
+    let mut port = 0;
+
+    for (i, behavior) in behaviors.iter().enumerate() {
+        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, i as u8 + 1));
+        let (socket, listener) = loop {
+            let socket = std::net::UdpSocket::bind(SocketAddr::new(ip, port)).unwrap();
+            let udp_port = socket.local_addr().unwrap().port();
+            match std::net::TcpListener::bind(SocketAddr::new(ip, udp_port)) {
+                Ok(listener) => {
+                    port = udp_port;
+                    break (socket, listener);
+                }
+                // NOTE: the TCP port of the first server might be taken
+                // by a server of another test
+                Err(_) if port == 0 => continue,
+                Err(err) => panic!("failed to listen on {ip}:{port}: {err}"),
+            }
+        };
+        socket.set_nonblocking(true).unwrap();
+        listener.set_nonblocking(true).unwrap();
+
+        tokio::spawn(serve_udp(UdpSocket::from_std(socket).unwrap(), *behavior));
+        tokio::spawn(serve_tcp(
+            TcpListener::from_std(listener).unwrap(),
+            *behavior,
+        ));
+        servers.push(ip);
+    }
+
+    (servers, port)
+}
+
+async fn serve_udp(socket: UdpSocket, mut behavior: Behavior) {
+    let mut buffer = [0; 512];
+    loop {
+        let (len, from) = socket.recv_from(&mut buffer).await.unwrap();
+        let request = Message::from_vec(&buffer[..len]).unwrap();
+
+        if let Some(response) = reply(&mut behavior, &request, from, false) {
+            let response = response.to_vec().unwrap();
+            socket.send_to(&response, from).await.unwrap();
+        }
+    }
+}
+
+async fn serve_tcp(listener: TcpListener, behavior: Behavior) {
+    let behavior = Arc::new(Mutex::new(behavior));
+    loop {
+        let (stream, from) = listener.accept().await.unwrap();
+        tokio::spawn(serve_stream(stream, from, behavior.clone()));
+    }
+}
+
+/// Replies to the queries received over a stream connection until
+/// it is closed.
+async fn serve_stream<S>(mut stream: S, from: SocketAddr, behavior: Arc<Mutex<Behavior>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut answered = false;
+    loop {
+        // NOTE: messages over TCP are prefixed with their length
+        let Ok(len) = stream.read_u16().await else {
+            return;
+        };
+        let mut buffer = vec![0; len as usize];
+        if stream.read_exact(&mut buffer).await.is_err() {
+            return;
+        }
+        let request = Message::from_vec(&buffer).unwrap();
+
+        let (response, closing) = {
+            let mut behavior = behavior.lock().unwrap();
+            let closing = matches!(*behavior, Behavior::Closing(_));
+            if answered && matches!(*behavior, Behavior::Stalling(_)) {
+                continue;
+            }
+            (reply(&mut behavior, &request, from, true), closing)
+        };
+        if let Some(response) = response {
+            answered = true;
+            let response = response.to_vec().unwrap();
+            if stream.write_u16(response.len() as u16).await.is_err()
+                || stream.write_all(&response).await.is_err()
+            {
+                return;
+            }
+            if closing {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Spawns a mock DNS-over-HTTPS server with a self-signed certificate,
+/// returning its address and the TLS settings which trust it.
+#[cfg(feature = "doh")]
+pub async fn spawn_https_server(mut behavior: Behavior) -> (SocketAddr, crate::dns::TlsConfig) {
+    use hyper::body::Bytes;
+
+    let (acceptor, tls) = tls_acceptor(&[b"h2"]);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, from) = listener.accept().await.unwrap();
+            let Ok(stream) = acceptor.accept(stream).await else {
+                continue;
+            };
+
+            let mut connection = h2::server::handshake(stream).await.unwrap();
+            while let Some(request) = connection.accept().await {
+                let (request, mut respond) = request.unwrap();
+
+                let mut body = request.into_body();
+                let mut buffer = Vec::new();
+                while let Some(chunk) = body.data().await {
+                    let chunk = chunk.unwrap();
+                    let _ = body.flow_control().release_capacity(chunk.len());
+                    buffer.extend_from_slice(&chunk);
+                }
+                let request = Message::from_vec(&buffer).unwrap();
+
+                let Some(response) = reply(&mut behavior, &request, from, true) else {
+                    continue;
+                };
+                let response = response.to_vec().unwrap();
+
+                let head = http::Response::builder()
+                    .header("content-type", "application/dns-message")
+                    .header("content-length", response.len())
+                    .body(())
+                    .unwrap();
+                let mut stream = respond.send_response(head, false).unwrap();
+                stream.send_data(Bytes::from(response), true).unwrap();
+            }
+        }
+    });
+
+    (addr, tls)
+}
+
+/// Spawns a mock DNS-over-TLS server with a self-signed certificate,
+/// returning its address and the TLS settings which trust it.
+#[cfg(feature = "dot")]
+pub async fn spawn_tls_server(behavior: Behavior) -> (SocketAddr, crate::dns::TlsConfig) {
+    let (acceptor, tls) = tls_acceptor(&[]);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let behavior = Arc::new(Mutex::new(behavior));
+    tokio::spawn(async move {
+        loop {
+            let (stream, from) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            let behavior = behavior.clone();
+            tokio::spawn(async move {
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    serve_stream(stream, from, behavior).await;
+                }
+            });
+        }
+    });
+
+    (addr, tls)
+}
+
+/// Creates a TLS acceptor with a self-signed certificate for [`TLS_SERVER_NAME`]
+/// and the client settings which trust it.
+#[cfg(any(feature = "doh", feature = "dot"))]
+fn tls_acceptor(alpn: &[&[u8]]) -> (tokio_rustls::TlsAcceptor, crate::dns::TlsConfig) {
+    let cert = rcgen::generate_simple_self_signed(vec![TLS_SERVER_NAME.to_owned()]).unwrap();
+    let cert_der = cert.serialize_der().unwrap();
+    let key_der = cert.serialize_private_key_der();
+
+    let mut server_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![rustls::Certificate(cert_der.clone())],
+            rustls::PrivateKey(key_der),
+        )
+        .unwrap();
+    server_config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&rustls::Certificate(cert_der)).unwrap();
+
+    (
+        tokio_rustls::TlsAcceptor::from(Arc::new(server_config)),
+        crate::dns::TlsConfig::new(TLS_SERVER_NAME).with_root_certificates(roots),
+    )
+}
+
+/// Returns `None` if the request must be ignored.
+fn reply(
+    behavior: &mut Behavior,
+    request: &Message,
+    from: SocketAddr,
+    tcp: bool,
+) -> Option<Message> {
+    let mut response = Message::new();
+    response
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .add_queries(request.queries().to_vec());
+
+    let addr = match behavior {
+        Behavior::Answer(addr)
+        | Behavior::Closing(addr)
+        | Behavior::Stalling(addr)
+        | Behavior::Flaky { drops: 0, addr } => Some(*addr),
+        Behavior::Empty => None,
+        Behavior::Echo => match from.ip() {
+            IpAddr::V4(addr) => Some(addr),
+            IpAddr::V6(_) => None,
+        },
+        Behavior::Sequence(addrs) => match addrs {
+            [addr] => *addr,
+            [addr, rest @ ..] => {
+                *addrs = rest;
+                *addr
+            }
+            [] => None,
+        },
+        Behavior::Truncated(addr) if tcp => Some(*addr),
+        Behavior::Truncated(_) => {
+            response.set_truncated(true);
+            None
+        }
+        Behavior::TcpOnly(addr) if tcp => Some(*addr),
+        Behavior::Port if tcp => Some(Ipv4Addr::from(u32::from(from.port()))),
+        Behavior::Flaky { drops, .. } => {
+            *drops -= 1;
+            return None;
+        }
+        Behavior::TcpOnly(_) | Behavior::Port | Behavior::Silent => return None,
+        Behavior::Chaos(addr) => {
+            let query = &request.queries()[0];
+            if query.query_class() == DNSClass::CH {
+                let ttl = TTL.as_secs() as u32;
+                let txt = RData::TXT(TXT::new(vec![addr.to_string()]));
+                let mut record = Record::from_rdata(query.name().clone(), ttl, txt);
+                record.set_dns_class(DNSClass::CH);
+                response.add_answer(record);
+            }
+            None
+        }
+    };
+
+    if let Some(addr) = addr {
+        let name = request.queries()[0].name().clone();
+        let ttl = TTL.as_secs() as u32;
+        response.add_answer(Record::from_rdata(name, ttl, RData::A(A(addr))));
+    }
+
+    Some(response)
+}
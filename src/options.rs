@@ -0,0 +1,174 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::dns::{self, Transport};
+use crate::{Bind, Store, Strategy};
+
+/// Options which control how the public IP address is resolved.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Options {
+    strategy: Strategy,
+    deadline: Option<Duration>,
+    timeout: Option<Duration>,
+    retries: usize,
+    backoff: Duration,
+    transport: Option<Transport>,
+    bind: Option<Bind>,
+    store: Option<Store>,
+    clients: Option<dns::Clients>,
+}
+
+impl Options {
+    /// Creates options with the sequential strategy, no deadline and no retries.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the way sources are queried.
+    #[must_use]
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the maximum time the whole resolution may take.
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the time to wait for a response from each server, overriding
+    /// the timeout of each resolver.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how many times a server is queried again after a failed query.
+    #[must_use]
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the delay before the first retry. The delay is doubled
+    /// after each subsequent retry.
+    #[must_use]
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the protocol used to send DNS queries, overriding the transport
+    /// of each DNS resolver.
+    ///
+    /// Resolvers without an explicit port use the default port of the transport.
+    #[must_use]
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Sets the local end which queries are sent from.
+    #[must_use]
+    pub fn with_bind(mut self, bind: Bind) -> Self {
+        self.bind = Some(bind);
+        self
+    }
+
+    /// Sets the store of the last known addresses, which is used when all
+    /// sources fail.
+    ///
+    /// See [`resolve_detailed`](crate::resolve_detailed) for details.
+    #[must_use]
+    pub fn with_store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Returns the way sources are queried.
+    #[must_use]
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Returns the maximum time the whole resolution may take.
+    #[must_use]
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// Returns the time to wait for a response from each server.
+    #[must_use]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns how many times a server is queried again after a failed query.
+    #[must_use]
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// Returns the delay before the first retry.
+    #[must_use]
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Returns the protocol used to send DNS queries.
+    #[must_use]
+    pub fn transport(&self) -> Option<&Transport> {
+        self.transport.as_ref()
+    }
+
+    /// Returns the local end which queries are sent from.
+    #[must_use]
+    pub fn bind(&self) -> Option<&Bind> {
+        self.bind.as_ref()
+    }
+
+    /// Returns the store of the last known addresses.
+    #[must_use]
+    pub fn store(&self) -> Option<&Store> {
+        self.store.as_ref()
+    }
+
+    /// Sets the pool of DNS clients which queries are sent through.
+    pub(crate) fn with_clients(mut self, clients: dns::Clients) -> Self {
+        self.clients = Some(clients);
+        self
+    }
+
+    /// Returns the pool of DNS clients which queries are sent through.
+    pub(crate) fn clients(&self) -> Option<&dns::Clients> {
+        self.clients.as_ref()
+    }
+}
+
+/// Runs `f` again up to `retries` times while it fails, doubling the
+/// `backoff` delay after each retry.
+pub(crate) async fn retry<T, E, F, Fut>(
+    retries: usize,
+    mut backoff: Duration,
+    mut f: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Err(_) if attempt < retries => {
+                crate::rt::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, BoxStream};
+use futures_util::{Stream, StreamExt};
+
+use crate::error::{Error, Failure};
+use crate::{dns, strategy, AddrVersion, Options, Resolution};
+
+/// A source of the public IP address, e.g. a cloud metadata service or
+/// the API of a router.
+///
+/// Use [`Source::Provider`](crate::Source::Provider) to query a provider
+/// along with the built-in resolvers.
+pub trait Provider: fmt::Debug + Send + Sync {
+    /// Returns the name which identifies the provider.
+    fn name(&self) -> &str;
+
+    /// Resolves the public IP address of the specified version.
+    fn resolve<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxFuture<'a, Result<Resolution, Error>>;
+
+    /// Resolves the public IP address of the specified version, yielding
+    /// the outcome of each query.
+    ///
+    /// The default implementation yields the outcome of [`Provider::resolve`].
+    /// Providers which query several servers should yield each answer, so
+    /// that strategies can tell the servers apart.
+    fn resolutions<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxStream<'a, Result<Resolution, Failure>> {
+        Box::pin(stream::once(async move {
+            self.resolve(version, options)
+                .await
+                .map_err(|error| Failure::new(self.name(), None, error))
+        }))
+    }
+}
+
+impl Provider for dns::Resolver<'_> {
+    fn name(&self) -> &str {
+        dns::Resolver::name(self)
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxFuture<'a, Result<Resolution, Error>> {
+        Box::pin(first_resolution(
+            version,
+            dns::Resolver::resolve(self, version, options),
+        ))
+    }
+
+    fn resolutions<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxStream<'a, Result<Resolution, Failure>> {
+        dns::Resolver::resolve(self, version, options)
+    }
+}
+
+#[cfg(feature = "http")]
+impl Provider for crate::http::Resolver<'_> {
+    fn name(&self) -> &str {
+        self.url()
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxFuture<'a, Result<Resolution, Error>> {
+        Box::pin(first_resolution(
+            version,
+            crate::http::Resolver::resolve(self, version, options),
+        ))
+    }
+
+    fn resolutions<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxStream<'a, Result<Resolution, Failure>> {
+        crate::http::Resolver::resolve(self, version, options)
+    }
+}
+
+#[cfg(feature = "stun")]
+impl Provider for crate::stun::Resolver<'_> {
+    fn name(&self) -> &str {
+        self.host()
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxFuture<'a, Result<Resolution, Error>> {
+        Box::pin(first_resolution(
+            version,
+            crate::stun::Resolver::resolve(self, version, options),
+        ))
+    }
+
+    fn resolutions<'a>(
+        &'a self,
+        version: AddrVersion,
+        options: &'a Options,
+    ) -> BoxStream<'a, Result<Resolution, Failure>> {
+        crate::stun::Resolver::resolve(self, version, options)
+    }
+}
+
+/// Returns the first answer of the specified version.
+async fn first_resolution<S>(version: AddrVersion, mut stream: S) -> Result<Resolution, Error>
+where
+    S: Stream<Item = Result<Resolution, Failure>> + Unpin,
+{
+    let mut failures = Vec::new();
+    while let Some(res) = stream.next().await {
+        match res {
+            Ok(resolution) if version.matches(resolution.addr) => return Ok(resolution),
+            Ok(resolution) => failures.push(Failure::new(
+                resolution.resolver,
+                Some(resolution.server),
+                Error::Version,
+            )),
+            Err(failure) => failures.push(failure),
+        }
+    }
+
+    Err(strategy::all_failed(failures))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use super::*;
+    use crate::mock::{self, Behavior};
+    use crate::Source;
+
+    const PUBLIC: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 7);
+
+    #[derive(Debug)]
+    struct Metadata(Option<IpAddr>);
+
+    impl Provider for Metadata {
+        fn name(&self) -> &str {
+            "metadata"
+        }
+
+        fn resolve<'a>(
+            &'a self,
+            _: AddrVersion,
+            _: &'a Options,
+        ) -> BoxFuture<'a, Result<Resolution, Error>> {
+            let server = SocketAddr::from(([169, 254, 169, 254], 80));
+            Box::pin(async move {
+                match self.0 {
+                    Some(addr) => Ok(Resolution::new(addr, self.name(), server)),
+                    None => Err(Error::Provider("no public address".into())),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn mixes_providers_with_resolvers() {
+        let resolver = mock::resolver(&[Behavior::Empty]);
+        let empty = Metadata(None);
+        let metadata = Metadata(Some(IpAddr::V4(PUBLIC)));
+
+        let sources = [
+            Source::from(&resolver),
+            Source::Provider(&empty),
+            Source::Provider(&metadata),
+        ];
+        let resolution = crate::resolve_detailed(AddrVersion::V4, &sources, &Options::new())
+            .await
+            .unwrap();
+        assert_eq!(resolution.addr, IpAddr::V4(PUBLIC));
+        assert_eq!(resolution.resolver, "metadata");
+    }
+
+    #[tokio::test]
+    async fn resolver_is_provider() {
+        let resolver = mock::resolver(&[Behavior::Silent, Behavior::Answer(PUBLIC)]);
+
+        let provider: &dyn Provider = &resolver;
+        let resolution = provider
+            .resolve(AddrVersion::V4, &Options::new())
+            .await
+            .unwrap();
+        assert_eq!(resolution.addr, IpAddr::V4(PUBLIC));
+        assert_eq!(provider.name(), mock::NAME);
+    }
+}
@@ -0,0 +1,45 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::dns::QueryMethod;
+
+/// A resolved public IP address along with the details of how it was resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Resolution {
+    /// The public IP address.
+    pub addr: IpAddr,
+    /// The name of the resolver which answered.
+    pub resolver: String,
+    /// The address of the server which answered.
+    pub server: SocketAddr,
+    /// The DNS query method, if the answer came from a DNS resolver.
+    pub method: Option<QueryMethod>,
+    /// The time it took to receive the answer, including retries.
+    pub latency: Duration,
+    /// The TTL of the DNS record, if the answer came from a DNS resolver.
+    pub ttl: Option<Duration>,
+    /// The age of the address if all sources failed and it is the last
+    /// known address loaded from the [`Store`](crate::Store).
+    pub stale: Option<Duration>,
+}
+
+impl Resolution {
+    /// Creates a resolution of the `addr` answered by the `server`, without
+    /// any other details.
+    #[must_use]
+    pub fn new<R>(addr: IpAddr, resolver: R, server: SocketAddr) -> Self
+    where
+        R: Into<String>,
+    {
+        Self {
+            addr,
+            resolver: resolver.into(),
+            server,
+            method: None,
+            latency: Duration::ZERO,
+            ttl: None,
+            stale: None,
+        }
+    }
+}
@@ -0,0 +1,516 @@
+//! Sockets, timers and blocking tasks of the runtime a query is polled
+//! within.
+//!
+//! With the `tokio` feature, queries polled within a Tokio runtime use that
+//! runtime. With the `async-io` feature, queries polled outside of one, e.g.
+//! on smol, async-std or `futures::executor`, use async-io, which works with
+//! any executor. With only the `tokio` feature, queries must be polled within
+//! a Tokio runtime.
+
+#[cfg(not(any(feature = "async-io", feature = "tokio")))]
+compile_error!("either the `tokio` or the `async-io` feature must be enabled");
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::future::{self, BoxFuture, Either};
+use futures_util::ready;
+use hickory_proto::tcp::DnsTcpStream;
+use hickory_proto::udp::DnsUdpSocket;
+
+/// The runtime which provides sockets and timers.
+#[derive(Debug, Clone, Copy)]
+enum Runtime {
+    #[cfg(feature = "tokio")]
+    Tokio,
+    #[cfg(feature = "async-io")]
+    AsyncIo,
+}
+
+impl Runtime {
+    /// Returns the runtime of the futures polled on the current thread.
+    fn current() -> Self {
+        #[cfg(all(feature = "tokio", feature = "async-io"))]
+        if tokio::runtime::Handle::try_current().is_err() {
+            return Self::AsyncIo;
+        }
+
+        #[cfg(feature = "tokio")]
+        {
+            Self::Tokio
+        }
+        #[cfg(not(feature = "tokio"))]
+        {
+            Self::AsyncIo
+        }
+    }
+}
+
+/// Waits until the `duration` has elapsed.
+pub(crate) async fn sleep(duration: Duration) {
+    match Runtime::current() {
+        #[cfg(feature = "tokio")]
+        Runtime::Tokio => tokio::time::sleep(duration).await,
+        #[cfg(feature = "async-io")]
+        Runtime::AsyncIo => {
+            async_io::Timer::after(duration).await;
+        }
+    }
+}
+
+/// Error returned by [`timeout`] once the duration has elapsed.
+#[derive(Debug)]
+pub(crate) struct Elapsed;
+
+/// Cancels the future unless it completes before the `duration` elapses.
+pub(crate) async fn timeout<F>(duration: Duration, fut: F) -> Result<F::Output, Elapsed>
+where
+    F: Future,
+{
+    match future::select(pin!(fut), pin!(sleep(duration))).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(((), _)) => Err(Elapsed),
+    }
+}
+
+/// Runs the function on a thread where blocking is acceptable.
+///
+/// The function runs to completion even if the returned future is dropped.
+pub(crate) fn spawn_blocking<F, R>(f: F) -> BoxFuture<'static, io::Result<R>>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    match Runtime::current() {
+        #[cfg(feature = "tokio")]
+        Runtime::Tokio => {
+            let handle = tokio::task::spawn_blocking(f);
+            Box::pin(async move { handle.await.map_err(io::Error::other) })
+        }
+        #[cfg(feature = "async-io")]
+        Runtime::AsyncIo => {
+            let (sender, receiver) = futures_channel::oneshot::channel();
+            blocking::unblock(move || drop(sender.send(f()))).detach();
+            Box::pin(async move { receiver.await.map_err(io::Error::other) })
+        }
+    }
+}
+
+/// Resolves the addresses of the `host` using the system resolver.
+pub(crate) async fn lookup_host(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    match Runtime::current() {
+        #[cfg(feature = "tokio")]
+        Runtime::Tokio => Ok(tokio::net::lookup_host((host, port)).await?.collect()),
+        #[cfg(feature = "async-io")]
+        Runtime::AsyncIo => {
+            use std::net::ToSocketAddrs;
+
+            let host = host.to_owned();
+            spawn_blocking(move || (host, port).to_socket_addrs().map(Iterator::collect)).await?
+        }
+    }
+}
+
+/// Timers of the runtime the DNS client is polled within.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Time;
+
+#[async_trait]
+impl hickory_proto::Time for Time {
+    async fn delay_for(duration: Duration) {
+        sleep(duration).await;
+    }
+
+    async fn timeout<F: 'static + Future + Send>(
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, io::Error> {
+        timeout(duration, future)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "future timed out"))
+    }
+}
+
+/// A UDP socket which can be polled to send and receive datagrams.
+///
+/// Implemented for the UDP sockets of Tokio and async-io, with the `tokio`
+/// and `async-io` features respectively.
+pub trait DatagramSocket {
+    /// Attempts to send the datagram in `buf` to the `target`.
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>>;
+
+    /// Attempts to receive a datagram into `buf`, returning its length and
+    /// the address it came from.
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>>;
+}
+
+/// Sends the datagram in `buf` to the `target`.
+#[cfg(feature = "stun")]
+pub(crate) async fn send_to<S>(socket: &S, buf: &[u8], target: SocketAddr) -> io::Result<usize>
+where
+    S: DatagramSocket + ?Sized,
+{
+    future::poll_fn(|cx| socket.poll_send_to(cx, buf, target)).await
+}
+
+/// Receives a datagram into `buf`.
+#[cfg(feature = "stun")]
+pub(crate) async fn recv_from<S>(socket: &S, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>
+where
+    S: DatagramSocket + ?Sized,
+{
+    future::poll_fn(|cx| socket.poll_recv_from(cx, buf)).await
+}
+
+#[cfg(feature = "tokio")]
+impl DatagramSocket for tokio::net::UdpSocket {
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        tokio::net::UdpSocket::poll_send_to(self, cx, buf, target)
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        let mut buf = tokio::io::ReadBuf::new(buf);
+        let from = ready!(tokio::net::UdpSocket::poll_recv_from(self, cx, &mut buf))?;
+        Poll::Ready(Ok((buf.filled().len(), from)))
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl DatagramSocket for async_io::Async<std::net::UdpSocket> {
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            match self.get_ref().send_to(buf, target) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.poll_writable(cx))?;
+                }
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        loop {
+            match self.get_ref().recv_from(buf) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.poll_readable(cx))?;
+                }
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+}
+
+/// A UDP socket registered with the runtime it was created within.
+pub(crate) enum UdpSocket {
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::net::UdpSocket),
+    #[cfg(feature = "async-io")]
+    AsyncIo(async_io::Async<std::net::UdpSocket>),
+}
+
+impl UdpSocket {
+    /// Registers the nonblocking `socket` with the current runtime.
+    pub(crate) fn new(socket: std::net::UdpSocket) -> io::Result<Self> {
+        match Runtime::current() {
+            #[cfg(feature = "tokio")]
+            Runtime::Tokio => tokio::net::UdpSocket::from_std(socket).map(Self::Tokio),
+            #[cfg(feature = "async-io")]
+            Runtime::AsyncIo => async_io::Async::new_nonblocking(socket).map(Self::AsyncIo),
+        }
+    }
+}
+
+impl DatagramSocket for UdpSocket {
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        match self {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(socket) => DatagramSocket::poll_send_to(socket, cx, buf, target),
+            #[cfg(feature = "async-io")]
+            Self::AsyncIo(socket) => socket.poll_send_to(cx, buf, target),
+        }
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        match self {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(socket) => DatagramSocket::poll_recv_from(socket, cx, buf),
+            #[cfg(feature = "async-io")]
+            Self::AsyncIo(socket) => socket.poll_recv_from(cx, buf),
+        }
+    }
+}
+
+impl DnsUdpSocket for UdpSocket {
+    type Time = Time;
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        DatagramSocket::poll_recv_from(self, cx, buf)
+    }
+
+    fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        DatagramSocket::poll_send_to(self, cx, buf, target)
+    }
+}
+
+/// A TCP stream registered with the runtime it was connected within.
+pub(crate) enum TcpStream {
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::net::TcpStream),
+    #[cfg(feature = "async-io")]
+    AsyncIo(async_io::Async<std::net::TcpStream>),
+}
+
+impl TcpStream {
+    /// Connects the nonblocking, not yet connected `socket` to the `server`.
+    pub(crate) async fn connect(
+        socket: std::net::TcpStream,
+        server: SocketAddr,
+    ) -> io::Result<Self> {
+        match Runtime::current() {
+            #[cfg(feature = "tokio")]
+            Runtime::Tokio => {
+                let socket = tokio::net::TcpSocket::from_std_stream(socket);
+                socket.connect(server).await.map(Self::Tokio)
+            }
+            #[cfg(feature = "async-io")]
+            Runtime::AsyncIo => {
+                match socket2::SockRef::from(&socket).connect(&server.into()) {
+                    Err(err) if !in_progress(&err) => return Err(err),
+                    _ => {}
+                }
+
+                // NOTE: the socket becomes writable once the connection is
+                // established or fails
+                let stream = async_io::Async::new_nonblocking(socket)?;
+                stream.writable().await?;
+                match stream.get_ref().take_error()? {
+                    Some(err) => Err(err),
+                    None => Ok(Self::AsyncIo(stream)),
+                }
+            }
+        }
+    }
+}
+
+/// Returns `true` if a nonblocking connect failed only because the
+/// connection is not established yet.
+#[cfg(feature = "async-io")]
+fn in_progress(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    if err.raw_os_error() == Some(libc::EINPROGRESS) {
+        return true;
+    }
+
+    err.kind() == io::ErrorKind::WouldBlock
+}
+
+impl futures_io::AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => poll_read_tokio(Pin::new(stream), cx, buf),
+            #[cfg(feature = "async-io")]
+            Self::AsyncIo(stream) => futures_io::AsyncRead::poll_read(Pin::new(stream), cx, buf),
+        }
+    }
+}
+
+impl futures_io::AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => tokio::io::AsyncWrite::poll_write(Pin::new(stream), cx, buf),
+            #[cfg(feature = "async-io")]
+            Self::AsyncIo(stream) => futures_io::AsyncWrite::poll_write(Pin::new(stream), cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => tokio::io::AsyncWrite::poll_flush(Pin::new(stream), cx),
+            #[cfg(feature = "async-io")]
+            Self::AsyncIo(stream) => futures_io::AsyncWrite::poll_flush(Pin::new(stream), cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => tokio::io::AsyncWrite::poll_shutdown(Pin::new(stream), cx),
+            #[cfg(feature = "async-io")]
+            Self::AsyncIo(stream) => futures_io::AsyncWrite::poll_close(Pin::new(stream), cx),
+        }
+    }
+}
+
+impl DnsTcpStream for TcpStream {
+    type Time = Time;
+}
+
+// NOTE: TLS and HTTP are layered on the stream with the I/O traits of
+// Tokio, which work on any runtime
+#[cfg(any(feature = "dot", feature = "http"))]
+impl tokio::io::AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            #[cfg(feature = "tokio")]
+            Self::Tokio(stream) => tokio::io::AsyncRead::poll_read(Pin::new(stream), cx, buf),
+            #[cfg(feature = "async-io")]
+            Self::AsyncIo(stream) => {
+                let len = ready!(futures_io::AsyncRead::poll_read(
+                    Pin::new(stream),
+                    cx,
+                    buf.initialize_unfilled()
+                ))?;
+                buf.advance(len);
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "dot", feature = "http"))]
+impl tokio::io::AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        futures_io::AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_io::AsyncWrite::poll_flush(self, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_io::AsyncWrite::poll_close(self, cx)
+    }
+}
+
+/// A stream with the I/O traits of Tokio, e.g. TLS layered on
+/// a [`TcpStream`], adapted to the DNS client.
+#[cfg(feature = "dot")]
+pub(crate) struct Compat<S>(pub(crate) S);
+
+#[cfg(feature = "dot")]
+impl<S> futures_io::AsyncRead for Compat<S>
+where
+    S: tokio::io::AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        poll_read_tokio(Pin::new(&mut self.0), cx, buf)
+    }
+}
+
+#[cfg(feature = "dot")]
+impl<S> futures_io::AsyncWrite for Compat<S>
+where
+    S: tokio::io::AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "dot")]
+impl<S> DnsTcpStream for Compat<S>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + Sync + 'static,
+{
+    type Time = Time;
+}
+
+/// Reads into `buf` from a stream with the I/O traits of Tokio.
+#[cfg(any(feature = "dot", feature = "tokio"))]
+fn poll_read_tokio<S>(
+    stream: Pin<&mut S>,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<usize>>
+where
+    S: tokio::io::AsyncRead,
+{
+    let mut buf = tokio::io::ReadBuf::new(buf);
+    ready!(stream.poll_read(cx, &mut buf))?;
+    Poll::Ready(Ok(buf.filled().len()))
+}
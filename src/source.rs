@@ -0,0 +1,77 @@
+use futures_util::stream::BoxStream;
+
+use crate::error::Failure;
+use crate::{dns, AddrVersion, Options, Provider, Resolution};
+
+/// A source of the public IP address.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum Source<'a> {
+    /// A DNS resolver.
+    Dns(&'a dns::Resolver<'a>),
+    /// An HTTP echo service.
+    #[cfg(feature = "http")]
+    Http(&'a crate::http::Resolver<'a>),
+    /// A STUN server.
+    #[cfg(feature = "stun")]
+    Stun(&'a crate::stun::Resolver<'a>),
+    /// A custom provider.
+    Provider(&'a dyn Provider),
+}
+
+impl<'a> Source<'a> {
+    /// Returns the name which identifies the source.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Dns(resolver) => resolver.name(),
+            #[cfg(feature = "http")]
+            Self::Http(resolver) => resolver.url(),
+            #[cfg(feature = "stun")]
+            Self::Stun(resolver) => resolver.host(),
+            Self::Provider(provider) => provider.name(),
+        }
+    }
+
+    /// Queries the source, yielding the outcome of each query.
+    pub fn resolve<'s>(
+        &'s self,
+        version: AddrVersion,
+        options: &'s Options,
+    ) -> BoxStream<'s, Result<Resolution, Failure>> {
+        match self {
+            Self::Dns(resolver) => resolver.resolve(version, options),
+            #[cfg(feature = "http")]
+            Self::Http(resolver) => resolver.resolve(version, options),
+            #[cfg(feature = "stun")]
+            Self::Stun(resolver) => resolver.resolve(version, options),
+            Self::Provider(provider) => provider.resolutions(version, options),
+        }
+    }
+}
+
+impl<'a> From<&'a dns::Resolver<'a>> for Source<'a> {
+    fn from(resolver: &'a dns::Resolver<'a>) -> Self {
+        Self::Dns(resolver)
+    }
+}
+
+#[cfg(feature = "http")]
+impl<'a> From<&'a crate::http::Resolver<'a>> for Source<'a> {
+    fn from(resolver: &'a crate::http::Resolver<'a>) -> Self {
+        Self::Http(resolver)
+    }
+}
+
+#[cfg(feature = "stun")]
+impl<'a> From<&'a crate::stun::Resolver<'a>> for Source<'a> {
+    fn from(resolver: &'a crate::stun::Resolver<'a>) -> Self {
+        Self::Stun(resolver)
+    }
+}
+
+impl<'a> From<&'a dyn Provider> for Source<'a> {
+    fn from(provider: &'a dyn Provider) -> Self {
+        Self::Provider(provider)
+    }
+}
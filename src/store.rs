@@ -0,0 +1,240 @@
+use std::ffi::OsString;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{rt, AddrVersion, Resolution};
+
+/// An on-disk store of the last known public IP address of each version,
+/// used when all sources fail.
+///
+/// The store is updated in the background after each successful
+/// resolution. Updates within a process are serialized, but a file shared
+/// by several processes may lose the updates of one of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    /// Creates a store which keeps the addresses in the file at `path`.
+    /// The file is created on the first successful resolution.
+    #[must_use]
+    pub fn new<P>(path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the last known resolution of the specified version, marked as
+    /// stale with its age.
+    pub(crate) async fn load(&self, version: AddrVersion) -> io::Result<Option<Resolution>> {
+        let store = self.clone();
+        blocking(move || store.load_blocking(version)).await
+    }
+
+    /// Replaces the last known resolution of the specified version in the
+    /// background, unless a later one was saved in the meantime.
+    ///
+    /// The returned future may be dropped without cancelling the update.
+    pub(crate) fn save(
+        &self,
+        version: AddrVersion,
+        resolution: &Resolution,
+    ) -> impl Future<Output = io::Result<io::Result<()>>> {
+        let store = self.clone();
+        let resolution = resolution.clone();
+        let resolved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        rt::spawn_blocking(move || store.save_blocking(version, &resolution, resolved_at))
+    }
+
+    fn load_blocking(&self, version: AddrVersion) -> io::Result<Option<Resolution>> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let Some(record) = contents
+            .lines()
+            .filter_map(Record::parse)
+            .find(|record| record.version == version_key(version) && version.matches(record.addr))
+        else {
+            return Ok(None);
+        };
+
+        let resolved_at = UNIX_EPOCH + Duration::from_secs(record.resolved_at);
+        let age = SystemTime::now()
+            .duration_since(resolved_at)
+            .unwrap_or_default();
+
+        Ok(Some(Resolution {
+            addr: record.addr,
+            resolver: record.resolver.to_owned(),
+            server: record.server,
+            method: None,
+            latency: Duration::ZERO,
+            ttl: None,
+            stale: Some(age),
+        }))
+    }
+
+    fn save_blocking(
+        &self,
+        version: AddrVersion,
+        resolution: &Resolution,
+        resolved_at: u64,
+    ) -> io::Result<()> {
+        // NOTE: concurrent updates of different versions must not overwrite
+        // each other
+        static WRITER: Mutex<()> = Mutex::new(());
+        let _writer = WRITER.lock().unwrap_or_else(|err| err.into_inner());
+
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut updated = String::new();
+        for line in contents.lines() {
+            match Record::parse(line) {
+                Some(record) if record.version != version_key(version) => {
+                    updated.push_str(line);
+                    updated.push('\n');
+                }
+                // NOTE: updates running in the background may finish out
+                // of order
+                Some(record) if record.resolved_at > resolved_at => return Ok(()),
+                _ => {}
+            }
+        }
+        updated.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            version_key(version),
+            resolution.addr,
+            resolution.server,
+            resolved_at,
+            resolution.resolver,
+        ));
+
+        // NOTE: the file is replaced atomically so that it is never read
+        // partially written
+        let mut tmp_path = OsString::from(&self.path);
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        std::fs::write(&tmp_path, updated)?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// Runs blocking file system calls on a thread where blocking is acceptable.
+async fn blocking<F, T>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    rt::spawn_blocking(f).await?
+}
+
+/// A line of the file: `<version> <addr> <server> <resolved at> <resolver>`,
+/// separated by tabs.
+struct Record<'a> {
+    version: &'a str,
+    addr: std::net::IpAddr,
+    server: std::net::SocketAddr,
+    resolved_at: u64,
+    resolver: &'a str,
+}
+
+impl<'a> Record<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+        Some(Self {
+            version: fields.next()?,
+            addr: fields.next()?.parse().ok()?,
+            server: fields.next()?.parse().ok()?,
+            resolved_at: fields.next()?.parse().ok()?,
+            resolver: fields.next()?,
+        })
+    }
+}
+
+fn version_key(version: AddrVersion) -> &'static str {
+    match version {
+        AddrVersion::V4 => "v4",
+        AddrVersion::V6 => "v6",
+        AddrVersion::Any => "any",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use futures_util::future;
+
+    use super::*;
+
+    const V4: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+    const V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7));
+
+    fn store(name: &str) -> Store {
+        let path = std::env::temp_dir().join(format!("getip-{}.{name}", std::process::id()));
+        Store::new(path)
+    }
+
+    fn resolution(addr: IpAddr) -> Resolution {
+        Resolution::new(addr, "test", SocketAddr::new(addr, 53))
+    }
+
+    #[tokio::test]
+    async fn keeps_concurrently_saved_versions() {
+        let (v4, v6) = (resolution(V4), resolution(V6));
+        let stores = [store("x.a"), store("x.b")];
+
+        for _ in 0..20 {
+            let saves = stores.iter().flat_map(|store| {
+                [
+                    store.save(AddrVersion::V4, &v4),
+                    store.save(AddrVersion::V6, &v6),
+                ]
+            });
+            for res in future::join_all(saves).await {
+                res.unwrap().unwrap();
+            }
+
+            for store in &stores {
+                let v4 = store.load(AddrVersion::V4).await.unwrap().unwrap();
+                assert_eq!(v4.addr, V4);
+                let v6 = store.load(AddrVersion::V6).await.unwrap().unwrap();
+                assert_eq!(v6.addr, V6);
+            }
+        }
+
+        for store in &stores {
+            std::fs::remove_file(store.path()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_addresses_of_other_version() {
+        let store = store("version");
+        std::fs::write(store.path(), format!("v4\t{V6}\t[{V6}]:53\t0\ttest\n")).unwrap();
+
+        assert!(store.load(AddrVersion::V4).await.unwrap().is_none());
+
+        std::fs::remove_file(store.path()).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use futures_util::stream::FuturesUnordered;
+use futures_util::{stream, StreamExt};
+
+use crate::error::{Error, Failure};
+use crate::{AddrVersion, Options, Resolution, Source};
+
+/// The way sources are queried.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Sources are queried one after another until one of them succeeds.
+    #[default]
+    Sequential,
+    /// All sources are queried concurrently, the first valid answer wins
+    /// and the remaining queries are cancelled.
+    Race,
+    /// All sources are queried concurrently and an address is returned
+    /// only when at least `quorum` of them agree on it.
+    Consensus {
+        /// The number of sources which must return the same address.
+        quorum: usize,
+    },
+}
+
+pub(crate) async fn sequential(
+    version: AddrVersion,
+    sources: &[Source<'_>],
+    options: &Options,
+) -> Result<Resolution, Error> {
+    let mut failures = Vec::new();
+
+    for source in sources {
+        let mut stream = source.resolve(version, options);
+        while let Some(res) = stream.next().await {
+            match res {
+                Ok(resolution) if version.matches(resolution.addr) => return Ok(resolution),
+                Ok(resolution) => failures.push(Failure::new(
+                    resolution.resolver,
+                    Some(resolution.server),
+                    Error::Version,
+                )),
+                Err(failure) => failures.push(failure),
+            }
+        }
+    }
+
+    Err(all_failed(failures))
+}
+
+pub(crate) async fn race(
+    version: AddrVersion,
+    sources: &[Source<'_>],
+    options: &Options,
+) -> Result<Resolution, Error> {
+    let mut failures = Vec::new();
+
+    // NOTE: dropping the merged stream cancels all pending queries
+    let mut stream = stream::select_all(
+        sources
+            .iter()
+            .map(|source| source.resolve(version, options)),
+    );
+    while let Some(res) = stream.next().await {
+        match res {
+            Ok(resolution) if version.matches(resolution.addr) => return Ok(resolution),
+            Ok(resolution) => failures.push(Failure::new(
+                resolution.resolver,
+                Some(resolution.server),
+                Error::Version,
+            )),
+            Err(failure) => failures.push(failure),
+        }
+    }
+
+    Err(all_failed(failures))
+}
+
+pub(crate) async fn consensus(
+    version: AddrVersion,
+    sources: &[Source<'_>],
+    options: &Options,
+    quorum: usize,
+) -> Result<Resolution, Error> {
+    let quorum = quorum.max(1);
+
+    let mut pending = sources
+        .iter()
+        .map(|source| {
+            let name = source.name().to_owned();
+            let mut stream = source.resolve(version, options);
+            async move {
+                let mut failures = Vec::new();
+                while let Some(res) = stream.next().await {
+                    match res {
+                        Ok(resolution) if version.matches(resolution.addr) => {
+                            return (Some((name, resolution)), failures);
+                        }
+                        Ok(resolution) => failures.push(Failure::new(
+                            resolution.resolver,
+                            Some(resolution.server),
+                            Error::Version,
+                        )),
+                        Err(failure) => failures.push(failure),
+                    }
+                }
+                (None, failures)
+            }
+        })
+        .collect::<FuturesUnordered<_>>();
+
+    let mut votes = HashMap::<IpAddr, usize>::new();
+    let mut answers = Vec::new();
+    let mut failures = Vec::new();
+    while let Some((answer, source_failures)) = pending.next().await {
+        failures.extend(source_failures);
+        let Some((name, resolution)) = answer else {
+            continue;
+        };
+
+        let count = votes.entry(resolution.addr).or_default();
+        *count += 1;
+        if *count >= quorum {
+            return Ok(resolution);
+        }
+
+        answers.push((name, resolution.addr));
+    }
+
+    // NOTE: without a single answer there is nothing to disagree on
+    if answers.is_empty() {
+        return Err(all_failed(failures));
+    }
+    Err(Error::NoConsensus { quorum, answers })
+}
+
+pub(crate) fn all_failed(failures: Vec<Failure>) -> Error {
+    if failures.is_empty() {
+        Error::Addr
+    } else {
+        Error::All(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::time::{Duration, Instant};
+
+    use futures_util::future::BoxFuture;
+
+    use super::*;
+    use crate::mock::{self, Behavior};
+    use crate::Provider;
+
+    #[derive(Debug)]
+    struct Fixed(IpAddr);
+
+    impl Provider for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn resolve<'a>(
+            &'a self,
+            _: AddrVersion,
+            _: &'a Options,
+        ) -> BoxFuture<'a, Result<Resolution, Error>> {
+            let server = SocketAddr::from(([192, 0, 2, 1], 80));
+            Box::pin(async move { Ok(Resolution::new(self.0, self.name(), server)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn sequential_skips_answers_of_other_version() {
+        let public = Ipv4Addr::new(203, 0, 113, 7);
+        let wrong = Fixed(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        let working = mock::resolver(&[Behavior::Answer(public)]);
+
+        let resolution = sequential(
+            AddrVersion::V4,
+            &[Source::Provider(&wrong), (&working).into()],
+            &Options::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolution.addr, IpAddr::V4(public));
+
+        let res = sequential(
+            AddrVersion::V4,
+            &[Source::Provider(&wrong)],
+            &Options::new(),
+        )
+        .await;
+        let Err(Error::All(failures)) = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert!(matches!(&failures[..], [f] if matches!(f.error, Error::Version)));
+    }
+
+    #[tokio::test]
+    async fn race_returns_first_valid_answer() {
+        let public = Ipv4Addr::new(203, 0, 113, 7);
+        let slow = mock::resolver(&[Behavior::Silent]).with_timeout(Duration::from_secs(5));
+        let broken = mock::resolver(&[Behavior::Empty]);
+        let working = mock::resolver(&[Behavior::Answer(public)]);
+
+        let started_at = Instant::now();
+        let resolution = race(
+            AddrVersion::V4,
+            &[(&slow).into(), (&broken).into(), (&working).into()],
+            &Options::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resolution.addr, IpAddr::V4(public));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn race_fails_when_all_fail() {
+        let first = mock::resolver(&[Behavior::Empty]);
+        let second = mock::resolver(&[Behavior::Silent]);
+
+        let res = race(
+            AddrVersion::V4,
+            &[(&first).into(), (&second).into()],
+            &Options::new(),
+        )
+        .await;
+        let Err(Error::All(failures)) = res else {
+            panic!("unexpected result: {res:?}");
+        };
+
+        assert_eq!(failures.len(), 2);
+        assert!(failures.iter().any(|f| matches!(f.error, Error::Addr)));
+        assert!(failures.iter().any(|f| matches!(f.error, Error::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn consensus_requires_quorum() {
+        let public = Ipv4Addr::new(203, 0, 113, 7);
+        let spoofed = Ipv4Addr::new(198, 51, 100, 1);
+        let first = mock::resolver(&[Behavior::Answer(public)]);
+        let second = mock::resolver(&[Behavior::Answer(spoofed)]);
+        let third = mock::resolver(&[Behavior::Empty, Behavior::Answer(public)]);
+
+        let resolution = consensus(
+            AddrVersion::V4,
+            &[(&first).into(), (&second).into(), (&third).into()],
+            &Options::new(),
+            2,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolution.addr, IpAddr::V4(public));
+    }
+
+    #[tokio::test]
+    async fn consensus_reports_disagreement() {
+        let public = Ipv4Addr::new(203, 0, 113, 7);
+        let spoofed = Ipv4Addr::new(198, 51, 100, 1);
+        let first = mock::resolver(&[Behavior::Answer(public)]);
+        let second = mock::resolver(&[Behavior::Answer(spoofed)]);
+        let third = mock::resolver(&[Behavior::Silent]);
+
+        let res = consensus(
+            AddrVersion::V4,
+            &[(&first).into(), (&second).into(), (&third).into()],
+            &Options::new(),
+            2,
+        )
+        .await;
+        let Err(Error::NoConsensus {
+            quorum,
+            mut answers,
+        }) = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+
+        answers.sort_by_key(|(_, addr)| *addr);
+        assert_eq!(quorum, 2);
+        assert_eq!(
+            answers,
+            [
+                (mock::NAME.to_owned(), IpAddr::V4(spoofed)),
+                (mock::NAME.to_owned(), IpAddr::V4(public)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn consensus_reports_failures_without_answers() {
+        let first = mock::resolver(&[Behavior::Empty]);
+        let second = mock::resolver(&[Behavior::Silent]);
+
+        let res = consensus(
+            AddrVersion::V4,
+            &[(&first).into(), (&second).into()],
+            &Options::new(),
+            2,
+        )
+        .await;
+        let Err(Error::All(failures)) = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(failures.len(), 2);
+    }
+}
@@ -0,0 +1,506 @@
+//! STUN based resolvers.
+//!
+//! Unlike other resolvers, a STUN server also reports the port which is
+//! mapped to the local socket by NATs on the way.
+
+use std::borrow::Cow;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::future;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+
+use crate::error::{Error, Failure};
+use crate::options::{self, Options};
+use crate::{rt, AddrVersion, Bind, Resolution};
+
+pub use crate::rt::DatagramSocket;
+
+const DEFAULT_STUN_PORT: u16 = 3478;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+const INITIAL_RTO: Duration = Duration::from_millis(500);
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const BINDING_ERROR: u16 = 0x0111;
+
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_ERROR_CODE: u16 = 0x0009;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+const FAMILY_V4: u8 = 0x01;
+const FAMILY_V6: u8 = 0x02;
+
+/// All built-in STUN resolvers.
+pub const ALL: &[&Resolver<'static>] = &[GOOGLE, CLOUDFLARE];
+
+/// Google STUN server.
+pub const GOOGLE: &Resolver<'static> = &Resolver::new_static("stun.l.google.com", 19302);
+
+/// Cloudflare STUN server.
+pub const CLOUDFLARE: &Resolver<'static> =
+    &Resolver::new_static("stun.cloudflare.com", DEFAULT_STUN_PORT);
+
+/// Options to build a STUN resolver.
+#[derive(Debug)]
+pub struct Resolver<'r> {
+    host: Cow<'r, str>,
+    port: u16,
+    timeout: Duration,
+}
+
+impl Resolver<'static> {
+    #[must_use]
+    const fn new_static(host: &'static str, port: u16) -> Self {
+        Self {
+            host: Cow::Borrowed(host),
+            port,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl<'r> Resolver<'r> {
+    /// Creates a resolver which sends binding requests to the `host`
+    /// using the default STUN port.
+    #[must_use]
+    pub fn new<H>(host: H) -> Self
+    where
+        H: Into<Cow<'r, str>>,
+    {
+        Self {
+            host: host.into(),
+            port: DEFAULT_STUN_PORT,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the port used to connect to the server.
+    #[must_use]
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Sets the time to wait for a response from each server.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the host of the STUN server.
+    #[must_use]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the port used to connect to the server.
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the time to wait for a response from each server.
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Queries the addresses of the host one by one, yielding the outcome
+    /// of each query.
+    pub fn resolve(
+        &self,
+        version: AddrVersion,
+        options: &Options,
+    ) -> BoxStream<'static, Result<Resolution, Failure>> {
+        let resolver = self.host.to_string();
+        Box::pin(self.bindings(version, options).map(move |res| {
+            res.map(|binding| Resolution {
+                addr: binding.mapped_addr.ip(),
+                resolver: resolver.clone(),
+                server: binding.server,
+                method: None,
+                latency: binding.latency,
+                ttl: None,
+                stale: None,
+            })
+        }))
+    }
+
+    /// Same as [`Resolver::resolve`], but yields the full mapped address
+    /// of a fresh socket.
+    pub fn mapped_addrs(
+        &self,
+        version: AddrVersion,
+        options: &Options,
+    ) -> BoxStream<'static, Result<SocketAddr, Failure>> {
+        Box::pin(
+            self.bindings(version, options)
+                .map(|res| res.map(|binding| binding.mapped_addr)),
+        )
+    }
+
+    fn bindings(
+        &self,
+        version: AddrVersion,
+        options: &Options,
+    ) -> BoxStream<'static, Result<Binding, Failure>> {
+        let host: Arc<str> = Arc::from(self.host.as_ref());
+        let port = self.port;
+        let timeout = options.timeout().unwrap_or(self.timeout);
+        let retries = options.retries();
+        let backoff = options.backoff();
+        let bind = options.bind().cloned();
+
+        let servers = {
+            let host = host.clone();
+            async move {
+                let lookup = rt::lookup_host(&host, port);
+                match rt::timeout(timeout, lookup).await {
+                    Ok(Ok(servers)) => Ok(servers
+                        .into_iter()
+                        .filter(|server| version.matches(server.ip()))
+                        .collect::<Vec<_>>()),
+                    Ok(Err(err)) => Err(Failure::new(host.as_ref(), None, err.into())),
+                    Err(_) => Err(Failure::new(host.as_ref(), None, Error::Timeout)),
+                }
+            }
+        };
+
+        Box::pin(stream::once(servers).flat_map(move |servers| {
+            let host = host.clone();
+            let bind = bind.clone();
+            match servers {
+                Ok(servers) => stream::iter(servers)
+                    .then(move |server| {
+                        let host = host.clone();
+                        let bind = bind.clone();
+                        async move {
+                            let started_at = Instant::now();
+                            match options::retry(retries, backoff, || {
+                                query(server, timeout, bind.as_ref())
+                            })
+                            .await
+                            {
+                                Ok(mapped_addr) => Ok(Binding {
+                                    server,
+                                    mapped_addr,
+                                    latency: started_at.elapsed(),
+                                }),
+                                Err(err) => Err(Failure::new(host.as_ref(), Some(server), err)),
+                            }
+                        }
+                    })
+                    .left_stream(),
+                Err(failure) => stream::once(future::ready(Err(failure))).right_stream(),
+            }
+        }))
+    }
+}
+
+struct Binding {
+    server: SocketAddr,
+    mapped_addr: SocketAddr,
+    latency: Duration,
+}
+
+/// Sends a binding request from the `socket` to the `server`, returning
+/// the address the server saw the request from.
+///
+/// The request is retransmitted until a response arrives, waiting half
+/// a second at first and twice as long after each retransmission, and
+/// fails with [`Error::Timeout`] once the `timeout` elapses.
+///
+/// Use this to learn the public address of a socket which is later used
+/// for peer-to-peer communication, e.g. a `tokio::net::UdpSocket` or
+/// an `async_io::Async<std::net::UdpSocket>`.
+pub async fn binding_request<S>(
+    socket: &S,
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<SocketAddr, Error>
+where
+    S: DatagramSocket + ?Sized,
+{
+    let transaction_id = rand::random::<[u8; 12]>();
+    let request = encode_request(&transaction_id);
+
+    let exchange = async {
+        let mut rto = INITIAL_RTO;
+        loop {
+            rt::send_to(socket, &request, server).await?;
+            // NOTE: a response to any of the transmissions will do, as they
+            // share the transaction id
+            if let Ok(res) = rt::timeout(rto, recv_response(socket, server, &transaction_id)).await
+            {
+                return res;
+            }
+            rto *= 2;
+        }
+    };
+
+    rt::timeout(timeout, exchange)
+        .await
+        .map_err(|_| Error::Timeout)?
+}
+
+/// Waits for the response to the request with the `transaction_id`.
+async fn recv_response<S>(
+    socket: &S,
+    server: SocketAddr,
+    transaction_id: &[u8; 12],
+) -> Result<SocketAddr, Error>
+where
+    S: DatagramSocket + ?Sized,
+{
+    let mut buffer = [0; 512];
+    loop {
+        let (len, from) = rt::recv_from(socket, &mut buffer).await?;
+        if from != server {
+            continue;
+        }
+
+        match decode_response(&buffer[..len], transaction_id) {
+            // NOTE: ignore stray packets which are not our responses
+            Ok(None) => continue,
+            Ok(Some(addr)) => return Ok(addr),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn query(
+    server: SocketAddr,
+    timeout: Duration,
+    bind: Option<&Bind>,
+) -> Result<SocketAddr, Error> {
+    let socket = crate::bind::udp_socket(bind, server)?;
+    binding_request(&socket, server, timeout).await
+}
+
+fn encode_request(transaction_id: &[u8; 12]) -> [u8; HEADER_LEN] {
+    let mut request = [0; HEADER_LEN];
+    request[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    // NOTE: bytes 2..4 are the message length which is zero
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(transaction_id);
+    request
+}
+
+/// Returns `None` if the message is not a response to our request.
+fn decode_response(message: &[u8], transaction_id: &[u8; 12]) -> Result<Option<SocketAddr>, Error> {
+    if message.len() < HEADER_LEN
+        || message[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || message[8..20] != transaction_id[..]
+    {
+        return Ok(None);
+    }
+
+    let message_type = u16::from_be_bytes([message[0], message[1]]);
+    let len = u16::from_be_bytes([message[2], message[3]]) as usize;
+    let Some(mut attributes) = message.get(HEADER_LEN..HEADER_LEN + len) else {
+        return Err(Error::Addr);
+    };
+
+    let mut mapped_addr = None;
+    let mut xor_mapped_addr = None;
+    let mut error_code = None;
+    while attributes.len() >= 4 {
+        let attr_type = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let attr_len = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let Some(value) = attributes.get(4..4 + attr_len) else {
+            return Err(Error::Addr);
+        };
+
+        match attr_type {
+            ATTR_MAPPED_ADDRESS => mapped_addr = parse_address(value, None),
+            ATTR_XOR_MAPPED_ADDRESS => xor_mapped_addr = parse_address(value, Some(message)),
+            ATTR_ERROR_CODE if value.len() >= 4 => {
+                error_code = Some(u16::from(value[2] & 0x7) * 100 + u16::from(value[3]));
+            }
+            _ => {}
+        }
+
+        // NOTE: attributes are padded to a multiple of 4 bytes
+        let padded_len = (4 + attr_len + 3) & !3;
+        attributes = attributes.get(padded_len..).unwrap_or_default();
+    }
+
+    match message_type {
+        BINDING_SUCCESS => xor_mapped_addr.or(mapped_addr).map(Some).ok_or(Error::Addr),
+        BINDING_ERROR => Err(Error::Stun(error_code.unwrap_or_default())),
+        _ => Ok(None),
+    }
+}
+
+/// Parses a `MAPPED-ADDRESS` value, or an `XOR-MAPPED-ADDRESS` value if
+/// the `header` it is xored with is specified.
+fn parse_address(value: &[u8], header: Option<&[u8]>) -> Option<SocketAddr> {
+    if value.len() < 4 {
+        return None;
+    }
+
+    let mut mask = [0; 16];
+    if let Some(header) = header {
+        mask.copy_from_slice(&header[4..20]);
+    }
+
+    let port = u16::from_be_bytes([value[2] ^ mask[0], value[3] ^ mask[1]]);
+    let ip = match (value[1], value.len()) {
+        (FAMILY_V4, 8) => {
+            let mut octets = [0; 4];
+            for (i, octet) in octets.iter_mut().enumerate() {
+                *octet = value[4 + i] ^ mask[i];
+            }
+            IpAddr::from(octets)
+        }
+        (FAMILY_V6, 20) => {
+            let mut octets = [0; 16];
+            for (i, octet) in octets.iter_mut().enumerate() {
+                *octet = value[4 + i] ^ mask[i];
+            }
+            IpAddr::from(octets)
+        }
+        _ => return None,
+    };
+
+    Some(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    /// Spawns a STUN server which ignores the first `drops` binding requests
+    /// and replies to the others with the address of the sender.
+    async fn spawn_server(mut drops: usize) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buffer = [0; 512];
+            loop {
+                let (len, from) = socket.recv_from(&mut buffer).await.unwrap();
+                let request = &buffer[..len];
+                assert_eq!(request[0..2], BINDING_REQUEST.to_be_bytes());
+                if drops > 0 {
+                    drops -= 1;
+                    continue;
+                }
+
+                let response = encode_response(&request[8..20], from);
+                socket.send_to(&response, from).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    fn encode_response(transaction_id: &[u8], addr: SocketAddr) -> Vec<u8> {
+        let mut mask = Vec::new();
+        mask.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        mask.extend_from_slice(transaction_id);
+
+        let (family, octets) = match addr.ip() {
+            IpAddr::V4(ip) => (FAMILY_V4, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (FAMILY_V6, ip.octets().to_vec()),
+        };
+        let port = addr.port() ^ (MAGIC_COOKIE >> 16) as u16;
+        let attr_len = 4 + octets.len() as u16;
+
+        let mut response = Vec::new();
+        response.extend_from_slice(&BINDING_SUCCESS.to_be_bytes());
+        response.extend_from_slice(&(4 + attr_len).to_be_bytes());
+        response.extend_from_slice(&mask);
+        response.extend_from_slice(&ATTR_XOR_MAPPED_ADDRESS.to_be_bytes());
+        response.extend_from_slice(&attr_len.to_be_bytes());
+        response.extend_from_slice(&[0, family]);
+        response.extend_from_slice(&port.to_be_bytes());
+        // NOTE: the address is masked by the magic cookie followed by
+        // the transaction id
+        for (octet, mask) in octets.iter().zip(&mask) {
+            response.push(octet ^ mask);
+        }
+        response
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn returns_mapped_address_of_socket() {
+        let server = spawn_server(0).await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let mapped = binding_request(&socket, server, DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(mapped, socket.local_addr().unwrap());
+    }
+
+    #[cfg(feature = "async-io")]
+    #[tokio::test]
+    async fn returns_mapped_address_of_async_io_socket() {
+        let server = spawn_server(0).await;
+        let socket = async_io::Async::<std::net::UdpSocket>::bind(([127, 0, 0, 1], 0)).unwrap();
+
+        let mapped = binding_request(&socket, server, DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(mapped, socket.get_ref().local_addr().unwrap());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn retransmits_lost_requests() {
+        let server = spawn_server(1).await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let mapped = binding_request(&socket, server, DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(mapped, socket.local_addr().unwrap());
+
+        let server = spawn_server(usize::MAX).await;
+        let res = binding_request(&socket, server, Duration::from_millis(200)).await;
+        assert!(matches!(res, Err(Error::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn resolves_as_source() {
+        let server = spawn_server(0).await;
+        let resolver = Resolver::new("127.0.0.1").with_port(server.port());
+
+        let addr = crate::resolve(AddrVersion::V4, &[(&resolver).into()], &Options::new())
+            .await
+            .unwrap();
+        assert_eq!(addr, IpAddr::V4(Ipv4Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn decodes_ipv6_address() {
+        let transaction_id = [7; 12];
+        let addr = SocketAddr::new(
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            4242,
+        );
+
+        let response = encode_response(&transaction_id, addr);
+        let decoded = decode_response(&response, &transaction_id).unwrap();
+        assert_eq!(decoded, Some(addr));
+    }
+
+    #[test]
+    fn ignores_foreign_transactions() {
+        let response = encode_response(&[1; 12], "127.0.0.1:1".parse().unwrap());
+        assert_eq!(decode_response(&response, &[2; 12]).unwrap(), None);
+    }
+}
@@ -0,0 +1,222 @@
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use futures_util::stream::{self, BoxStream};
+
+use crate::{AddrVersion, Options, Source, DEFAULT};
+
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Periodically resolves the public IP address of the specified version
+/// using the built-in resolvers, yielding only its changes.
+///
+/// See [`Watcher`] for more options.
+pub fn watch(interval: Duration, version: AddrVersion) -> BoxStream<'static, Change> {
+    Watcher::new(interval).watch(version)
+}
+
+/// A change of the public IP address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Change {
+    /// The previous address, `None` for the first resolved address.
+    pub old: Option<IpAddr>,
+    /// The new address.
+    pub new: IpAddr,
+    /// The time the new address was confirmed.
+    pub at: SystemTime,
+}
+
+/// Options to build a stream of public IP address changes.
+#[derive(Debug, Clone)]
+pub struct Watcher<'a> {
+    sources: &'a [Source<'a>],
+    options: Options,
+    interval: Duration,
+    jitter: Duration,
+    max_backoff: Duration,
+    confirmations: usize,
+}
+
+impl Watcher<'static> {
+    /// Creates a watcher which resolves the address every `interval`
+    /// using the built-in resolvers.
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            sources: DEFAULT,
+            options: Options::new(),
+            interval,
+            jitter: Duration::ZERO,
+            max_backoff: DEFAULT_MAX_BACKOFF.max(interval),
+            confirmations: 1,
+        }
+    }
+}
+
+impl<'a> Watcher<'a> {
+    /// Sets the sources which are queried.
+    #[must_use]
+    pub fn with_sources<'b>(self, sources: &'b [Source<'b>]) -> Watcher<'b> {
+        Watcher {
+            sources,
+            options: self.options,
+            interval: self.interval,
+            jitter: self.jitter,
+            max_backoff: self.max_backoff,
+            confirmations: self.confirmations,
+        }
+    }
+
+    /// Sets the options used for each resolution.
+    #[must_use]
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the maximum random delay added to each interval, so that
+    /// many watchers don't query the sources at the same time.
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the maximum delay between failed resolutions. The delay starts
+    /// at the interval and is doubled after each consecutive failure.
+    #[must_use]
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets how many consecutive resolutions must return a new address
+    /// before the change is reported. The first resolved address is
+    /// reported immediately.
+    #[must_use]
+    pub fn with_confirmations(mut self, confirmations: usize) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Starts resolving the address. Failed resolutions are not reported.
+    pub fn watch(self, version: AddrVersion) -> BoxStream<'a, Change> {
+        let state = State {
+            watcher: self,
+            current: None,
+            candidate: None,
+            delay: None,
+        };
+
+        Box::pin(stream::unfold(state, move |mut state| async move {
+            let change = state.next(version).await;
+            Some((change, state))
+        }))
+    }
+}
+
+struct State<'a> {
+    watcher: Watcher<'a>,
+    current: Option<IpAddr>,
+    /// A new address and the number of times it was resolved in a row.
+    candidate: Option<(IpAddr, usize)>,
+    /// The delay before the next resolution, `None` before the first one.
+    delay: Option<Duration>,
+}
+
+impl State<'_> {
+    async fn next(&mut self, version: AddrVersion) -> Change {
+        let watcher = &self.watcher;
+        loop {
+            if let Some(delay) = self.delay {
+                crate::rt::sleep(delay + jitter(watcher.jitter)).await;
+            }
+
+            let addr = match crate::resolve(version, watcher.sources, &watcher.options).await {
+                Ok(addr) => {
+                    self.delay = Some(watcher.interval);
+                    addr
+                }
+                Err(_) => {
+                    self.delay = Some(match self.delay {
+                        Some(delay) => (delay * 2).min(watcher.max_backoff),
+                        None => watcher.interval,
+                    });
+                    continue;
+                }
+            };
+
+            if self.current == Some(addr) {
+                self.candidate = None;
+                continue;
+            }
+
+            let confirmations = match self.candidate {
+                Some((candidate, confirmations)) if candidate == addr => confirmations + 1,
+                _ => 1,
+            };
+
+            if self.current.is_some() && confirmations < watcher.confirmations {
+                self.candidate = Some((addr, confirmations));
+                continue;
+            }
+
+            self.candidate = None;
+            return Change {
+                old: self.current.replace(addr),
+                new: addr,
+                at: SystemTime::now(),
+            };
+        }
+    }
+}
+
+fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    max.mul_f64(rand::random::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::mock::{self, Behavior};
+
+    const A: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+    const B: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 2);
+
+    #[tokio::test]
+    async fn reports_confirmed_changes() {
+        let resolver = mock::resolver(&[Behavior::Sequence(&[
+            Some(A),
+            None,
+            Some(A),
+            Some(B),
+            Some(A),
+            Some(B),
+            Some(B),
+        ])]);
+
+        let sources = [Source::from(&resolver)];
+        let changes: Vec<_> = Watcher::new(Duration::from_millis(10))
+            .with_sources(&sources)
+            .with_max_backoff(Duration::from_millis(20))
+            .with_confirmations(2)
+            .watch(AddrVersion::V4)
+            .take(2)
+            .map(|change| (change.old, change.new))
+            .collect()
+            .await;
+
+        assert_eq!(
+            changes,
+            [(None, IpAddr::V4(A)), (Some(IpAddr::V4(A)), IpAddr::V4(B)),]
+        );
+    }
+}